@@ -0,0 +1,143 @@
+//! Packing independent solutions into shared solution sets.
+//!
+//! Every solution in a set is checked against the same pre-state and the same post-state,
+//! so a set may not mutate one key twice, and a solution that reads a key another solution
+//! of the set writes, like an order add checking a balance a deposit in the set changes,
+//! would see the value from before the set. A [`Batcher`] takes solutions in the order they
+//! are meant to be applied and closes the current set as soon as the next solution's
+//! [`Footprint`] overlaps it, or the set is full. Solutions are never reordered, so one that
+//! depends on an earlier one through a shared key always lands in a later block.
+//!
+//! Footprints come from [`crate::conflicts`], which knows the keys each predicate of the
+//! standard contract reads; solutions for other predicates are compared by their writes.
+use crate::conflicts::{Footprint, footprint};
+use crate::error::Result;
+use essential_types::solution::{Solution, SolutionSet};
+
+/// Solutions per set when nothing else is asked for.
+pub const DEFAULT_BATCH: usize = 100;
+
+/// Whether `a` and `b` touch a common key, one of them writing it, and so cannot share a
+/// solution set.
+pub fn conflicts(a: &Solution, b: &Solution) -> Result<bool> {
+    Ok(footprint(a)?.overlaps(&footprint(b)?))
+}
+
+/// Groups solutions, in order, into solution sets without key conflicts.
+#[derive(Debug, Clone)]
+pub struct Batcher {
+    max_solutions: usize,
+    solutions: Vec<Solution>,
+    open: Footprint,
+}
+
+impl Default for Batcher {
+    fn default() -> Self {
+        Self::new(DEFAULT_BATCH)
+    }
+}
+
+impl Batcher {
+    /// A batcher closing sets at `max_solutions` solutions, at least one.
+    pub fn new(max_solutions: usize) -> Self {
+        Self { max_solutions: max_solutions.max(1), solutions: vec![], open: Footprint::default() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.solutions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.solutions.is_empty()
+    }
+
+    /// Whether `solution` reads or writes a key the open set writes, or writes one it reads.
+    pub fn conflicts(&self, solution: &Solution) -> Result<bool> {
+        Ok(self.open.overlaps(&footprint(solution)?))
+    }
+
+    /// Adds `solution` to the open set. If it cannot join, the open set is closed and
+    /// returned and `solution` starts the next one.
+    pub fn push(&mut self, solution: Solution) -> Result<Option<SolutionSet>> {
+        let footprint = footprint(&solution)?;
+        let closed = if self.len() >= self.max_solutions || self.open.overlaps(&footprint) { self.flush() } else { None };
+        self.open.extend(&footprint);
+        self.solutions.push(solution);
+        Ok(closed)
+    }
+
+    /// Closes the open set, if it has any solutions.
+    pub fn flush(&mut self) -> Option<SolutionSet> {
+        if self.is_empty() {
+            return None;
+        }
+        self.open = Footprint::default();
+        Some(SolutionSet { solutions: std::mem::take(&mut self.solutions) })
+    }
+}
+
+/// Packs `solutions` into as few solution sets as their key conflicts allow, keeping their
+/// order, each with at most `max_solutions` solutions.
+pub fn pack(solutions: impl IntoIterator<Item = Solution>, max_solutions: usize) -> Result<Vec<SolutionSet>> {
+    let mut batcher = Batcher::new(max_solutions);
+    let mut sets = vec![];
+    for solution in solutions {
+        sets.extend(batcher.push(solution)?);
+    }
+    sets.extend(batcher.flush());
+    Ok(sets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solutions::{LimitOrder, produce_solution_add_limit_order_ask, produce_solution_add_limit_order_bid, produce_solution_deposit};
+
+    fn deposit(addr: i64) -> Solution {
+        produce_solution_deposit(100, 100, 10, 10, [addr; 4], [addr; 4], [addr; 4])
+    }
+
+    fn add_bid(leading_key: i64, new_index: i64) -> Solution {
+        let order = LimitOrder { max_amnt: 1, price: 100, is_bid: true, addr: [new_index; 4], auth: [new_index; 4], next_key: 0 };
+        produce_solution_add_limit_order_bid(leading_key, 0, order, new_index, new_index, new_index)
+    }
+
+    fn add_ask(leading_key: i64, new_index: i64) -> Solution {
+        let order = LimitOrder { max_amnt: 1, price: 100, is_bid: false, addr: [new_index; 4], auth: [new_index; 4], next_key: 0 };
+        produce_solution_add_limit_order_ask(leading_key, 0, order, new_index, new_index, new_index)
+    }
+
+    #[test]
+    fn deposits_for_distinct_addresses_share_a_set() {
+        let sets = pack((1..=5).map(deposit), DEFAULT_BATCH).unwrap();
+        assert_eq!(sets.len(), 1);
+        assert_eq!(sets[0].solutions.len(), 5);
+
+        // the second deposit for 1 waits for the first, and so does everything after it
+        let sets = pack([deposit(1), deposit(2), deposit(1), deposit(3)], DEFAULT_BATCH).unwrap();
+        assert_eq!(sets.iter().map(|set| set.solutions.len()).collect::<Vec<_>>(), vec![2, 2]);
+
+        let sets = pack((1..=5).map(deposit), 2).unwrap();
+        assert_eq!(sets.iter().map(|set| set.solutions.len()).collect::<Vec<_>>(), vec![2, 2, 1]);
+    }
+
+    #[test]
+    fn adds_behind_different_orders_share_a_set() {
+        // only an add at the front writes the head
+        assert!(!conflicts(&add_bid(1, 10), &add_bid(2, 11)).unwrap());
+        assert!(conflicts(&add_bid(0, 10), &add_bid(0, 11)).unwrap());
+        assert!(!conflicts(&add_bid(0, 10), &add_bid(2, 11)).unwrap());
+        // the second one is linked behind the first
+        assert!(conflicts(&add_bid(1, 10), &add_bid(10, 11)).unwrap());
+        assert!(!conflicts(&add_bid(1, 10), &add_ask(1, 11)).unwrap());
+
+        let sets = pack([add_bid(1, 10), add_bid(2, 11), add_ask(0, 12), add_bid(3, 13)], DEFAULT_BATCH).unwrap();
+        assert_eq!(sets.len(), 1);
+    }
+
+    #[test]
+    fn an_add_waits_for_the_deposit_it_reads() {
+        let sets = pack([deposit(10), add_bid(1, 10), add_bid(2, 11)], DEFAULT_BATCH).unwrap();
+        assert_eq!(sets.iter().map(|set| set.solutions.len()).collect::<Vec<_>>(), vec![1, 2]);
+    }
+}
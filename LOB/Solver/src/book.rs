@@ -0,0 +1,619 @@
+//! Off-chain mirror of the contract's linked-list orderbook.
+use crate::solutions::{
+    INSERT_RUN, LimitOrder, produce_solution_add_limit_order_ask, produce_solution_add_limit_order_bid,
+    produce_solution_add_limit_orders_batch, produce_solution_remove_limit_order_ask,
+    produce_solution_remove_limit_order_bid,
+};
+use crate::variant::ContractVariant;
+use crate::error::{Result, SolverError};
+use crate::fixed::{Decimals, notional};
+use essential_types::{Word, solution::{Solution, SolutionSet}};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+/// A resting limit order as tracked by the mirror.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Order {
+    /// Slot of the order in `bid_orders`/`ask_orders`.
+    pub index: i64,
+    /// Quantity in lots of token1.
+    pub max_amnt: i64,
+    /// Token0 base units per lot.
+    pub price: i64,
+    pub is_bid: bool,
+    pub addr: [Word; 4],
+    pub auth: [Word; 4],
+}
+
+/// Orders resting at one price, earliest order at the front.
+pub type PriceLevel = VecDeque<Order>; // earliest order at the front
+
+/// Price levels of both sides of the book, keyed by price.
+///
+/// On chain, bids are linked from the highest price down and asks from the lowest price up,
+/// each level in time priority.
+#[derive(Debug, Default, Clone)]
+pub struct OrderBook {
+    pub bids: BTreeMap<u64, PriceLevel>, // descending when matching
+    pub asks: BTreeMap<u64, PriceLevel>, // ascending when matching
+}
+
+impl Order {
+    /// Builds an order from decimal `price` and `quantity`, encoded with `decimals`.
+    pub fn from_decimal(
+        index: i64,
+        price: f64,
+        quantity: f64,
+        is_bid: bool,
+        addr: [Word; 4],
+        auth: [Word; 4],
+        decimals: &Decimals,
+    ) -> Result<Order> {
+        Ok(Order {
+            index,
+            max_amnt: decimals.quantity_to_chain(quantity)?,
+            price: decimals.price_to_chain(price)?,
+            is_bid,
+            addr,
+            auth,
+        })
+    }
+
+    /// Token0 base units needed to fill the whole order.
+    pub fn notional(&self) -> Result<i64> {
+        notional(self.max_amnt, self.price)
+    }
+}
+
+impl OrderBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Price levels of one side.
+    pub fn side(&self, is_bid: bool) -> &BTreeMap<u64, PriceLevel> {
+        if is_bid { &self.bids } else { &self.asks }
+    }
+
+    pub fn side_mut(&mut self, is_bid: bool) -> &mut BTreeMap<u64, PriceLevel> {
+        if is_bid { &mut self.bids } else { &mut self.asks }
+    }
+
+    /// All orders of one side in on-chain linked list order.
+    pub fn chain(&self, is_bid: bool) -> Vec<&Order> {
+        if is_bid {
+            self.bids.values().rev().flat_map(|level| level.iter()).collect()
+        } else {
+            self.asks.values().flat_map(|level| level.iter()).collect()
+        }
+    }
+
+    /// Index of the first (best) bid, 0 if there is none. Mirrors `first_bid_order`.
+    pub fn first_bid_index(&self) -> i64 {
+        self.chain(true).first().map_or(0, |order| order.index)
+    }
+
+    /// Index of the first (best) ask, 0 if there is none. Mirrors `first_ask_order`.
+    pub fn first_ask_index(&self) -> i64 {
+        self.chain(false).first().map_or(0, |order| order.index)
+    }
+
+    /// Index of the order following `index` in the linked list, 0 if it is the last one.
+    pub fn next_index(&self, index: i64, is_bid: bool) -> i64 {
+        let orders = self.chain(is_bid);
+        orders
+            .iter()
+            .position(|o| o.index == index)
+            .and_then(|i| orders.get(i + 1))
+            .map_or(0, |o| o.index)
+    }
+
+    /// Index of the first order left on one side once the orders in `settled` are gone.
+    pub fn first_index_excluding(&self, is_bid: bool, settled: &[i64]) -> i64 {
+        self.chain(is_bid)
+            .into_iter()
+            .find(|order| !settled.contains(&order.index))
+            .map_or(0, |order| order.index)
+    }
+
+    /// Looks an order up by its index.
+    pub fn get(&self, index: i64, is_bid: bool) -> Option<&Order> {
+        self.side(is_bid)
+            .values()
+            .flat_map(|level| level.iter())
+            .find(|order| order.index == index)
+    }
+
+    /// Works out where `order` goes in the linked list: behind every order at a better or
+    /// equal price, in front of every order at a worse price. Fails for a price the
+    /// mirror cannot file under a level, i.e. one that is not positive.
+    pub fn plan_insert(&self, order: Order) -> Result<InsertPlan> {
+        if order.price <= 0 {
+            return Err(SolverError::Validation(format!("order {} has price {}", order.index, order.price)));
+        }
+        let price = order.price as u64;
+        let levels = self.side(order.is_bid);
+        // bids are linked from the highest price down, asks from the lowest price up
+        let (leading, trailing) = if order.is_bid {
+            (
+                levels.range(price..).next().and_then(|(_, l)| l.back()),
+                levels.range(..price).next_back().and_then(|(_, l)| l.front()),
+            )
+        } else {
+            (
+                levels.range(..=price).next_back().and_then(|(_, l)| l.back()),
+                levels.range(price + 1..).next().and_then(|(_, l)| l.front()),
+            )
+        };
+        let leading_key = leading.map_or(0, |o| o.index);
+        let trailing_key = trailing.map_or(0, |o| o.index);
+        let first_order = if order.is_bid { self.first_bid_index() } else { self.first_ask_index() };
+        Ok(InsertPlan {
+            leading_key,
+            trailing_key,
+            leading_order_next: if leading_key != 0 { order.index } else { 0 },
+            first_order_index: if leading_key == 0 { order.index } else { first_order },
+            order,
+        })
+    }
+
+    /// Works out where a batch of new orders goes, e.g. several orders at one price level
+    /// or a ladder of levels on either side, so they can be added in one solution set.
+    /// Orders at the same price keep the order they are given in.
+    ///
+    /// New orders that end up next to each other in the linked list form a run, which one
+    /// `addLimitOrdersBid`/`addLimitOrdersAsk` solution links in with all its intermediate
+    /// pointers. Runs longer than [`INSERT_RUN`] are split, and each further part is
+    /// linked behind the previous one. Every run writes the head of its side, so a side
+    /// takes one run or part per block and the bid and ask runs go in side by side.
+    pub fn plan_ladder(&self, orders: Vec<Order>) -> Result<LadderPlan> {
+        self.plan_ladder_with(orders, INSERT_RUN)
+    }
+
+    /// [`Self::plan_ladder`] for a contract whose runs take `insert_run` orders.
+    pub fn plan_ladder_with(&self, orders: Vec<Order>, insert_run: usize) -> Result<LadderPlan> {
+        if insert_run == 0 {
+            return Err(SolverError::Validation("runs need room for at least one order".to_string()));
+        }
+        let mut new = HashSet::new();
+        for order in &orders {
+            let taken = self.get(order.index, true).is_some() || self.get(order.index, false).is_some();
+            if order.index <= 0 || taken || !new.insert(order.index) {
+                return Err(SolverError::Validation(format!("order index {} is not free", order.index)));
+            }
+        }
+
+        let mut after = self.clone();
+        for order in &orders {
+            after.insert(order.clone());
+        }
+        let mut runs = vec![];
+        for is_bid in [true, false] {
+            let mut side = vec![];
+            let mut leading_key = 0;
+            let mut run: Vec<Order> = vec![];
+            // a resting order, or the end of the book, closes the run in front of it
+            for order in after.chain(is_bid).into_iter().map(Some).chain([None]) {
+                match order {
+                    Some(order) if new.contains(&order.index) => run.push(order.clone()),
+                    _ => {
+                        let trailing_key = order.map_or(0, |o| o.index);
+                        split_run(std::mem::take(&mut run), is_bid, leading_key, trailing_key, insert_run, &mut side);
+                        leading_key = trailing_key;
+                    }
+                }
+            }
+            // one run per block, each writing the head the side has once it is in
+            let mut first_order_index = if is_bid { self.first_bid_index() } else { self.first_ask_index() };
+            for (block, run) in side.iter_mut().enumerate() {
+                if run.leading_key == 0 {
+                    first_order_index = run.orders[0].index;
+                }
+                run.block = block;
+                run.first_order_index = first_order_index;
+            }
+            runs.extend(side);
+        }
+        Ok(LadderPlan { orders, runs, insert_run })
+    }
+
+    /// Takes the order with `index` out of the bid or ask side of the mirror and plans its
+    /// removal on chain. `None` if that side has no such order.
+    ///
+    /// If the solution does not make it into a block, hand the plan to [`CancelPlan::undo`]
+    /// to put the order back where it was.
+    pub fn plan_cancel(&mut self, index: i64, is_bid: bool) -> Option<CancelPlan> {
+        let orders = self.chain(is_bid);
+        let position = orders.iter().position(|o| o.index == index)?;
+        let leading_key = position.checked_sub(1).map_or(0, |i| orders[i].index);
+        let trailing_key = orders.get(position + 1).map_or(0, |o| o.index);
+        let first_order_index = if leading_key == 0 { trailing_key } else { orders[0].index };
+        let level_position = self.side(is_bid)[&(orders[position].price as u64)]
+            .iter()
+            .position(|o| o.index == index)?;
+        let order = self.remove(index, is_bid)?;
+        Some(CancelPlan {
+            order,
+            level_position,
+            leading_key,
+            trailing_key,
+            leading_order_next: if leading_key != 0 { trailing_key } else { 0 },
+            first_order_index,
+        })
+    }
+
+    /// Appends `order` to the back of its price level.
+    pub fn insert(&mut self, order: Order) {
+        let price = order.price as u64;
+        self.side_mut(order.is_bid).entry(price).or_default().push_back(order);
+    }
+
+    /// Removes the order with `index` and drops its level if it became empty.
+    pub fn remove(&mut self, index: i64, is_bid: bool) -> Option<Order> {
+        let levels = self.side_mut(is_bid);
+        let price = *levels
+            .iter()
+            .find(|(_, level)| level.iter().any(|o| o.index == index))?
+            .0;
+        let level = levels.get_mut(&price)?;
+        let position = level.iter().position(|o| o.index == index)?;
+        let order = level.remove(position);
+        if level.is_empty() {
+            levels.remove(&price);
+        }
+        order
+    }
+
+    /// Reduces the order with `index` by `amount`, removing it once nothing is left.
+    pub fn fill(&mut self, index: i64, is_bid: bool, amount: i64) {
+        let levels = self.side_mut(is_bid);
+        let Some((&price, level)) = levels
+            .iter_mut()
+            .find(|(_, level)| level.iter().any(|o| o.index == index))
+        else {
+            return;
+        };
+        if let Some(order) = level.iter_mut().find(|o| o.index == index) {
+            order.max_amnt -= amount;
+        }
+        level.retain(|o| o.max_amnt > 0);
+        if level.is_empty() {
+            levels.remove(&price);
+        }
+    }
+}
+
+/// Linkage fields of an `addLimitOrderBid`/`addLimitOrderAsk` solution for one new order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InsertPlan {
+    pub order: Order,
+    /// Order the new one is linked behind, 0 if it becomes the first order.
+    pub leading_key: i64,
+    /// Order the new one points to, 0 if it becomes the last order.
+    pub trailing_key: i64,
+    pub leading_order_next: i64,
+    pub first_order_index: i64,
+}
+
+impl InsertPlan {
+    /// The new order as it is stored on chain.
+    pub fn limit_order(&self) -> LimitOrder {
+        LimitOrder {
+            max_amnt: self.order.max_amnt,
+            price: self.order.price,
+            is_bid: self.order.is_bid,
+            addr: self.order.addr,
+            auth: self.order.auth,
+            next_key: self.trailing_key,
+        }
+    }
+
+    pub fn to_solution(&self) -> Solution {
+        let build = if self.order.is_bid {
+            produce_solution_add_limit_order_bid
+        } else {
+            produce_solution_add_limit_order_ask
+        };
+        build(
+            self.leading_key,
+            self.trailing_key,
+            self.limit_order(),
+            self.order.index,
+            self.leading_order_next,
+            self.first_order_index,
+        )
+    }
+
+    /// Adds the order to the mirror, once its block has been built.
+    pub fn apply(&self, orderbook: &mut OrderBook) {
+        orderbook.insert(self.order.clone());
+    }
+}
+
+// Cuts a run into parts of at most `insert_run` orders. Every part points at the run's
+// trailing order, the first part is linked behind the leading one and each further part
+// behind the last order of the part before it. Blocks and heads are filled in by the caller.
+fn split_run(run: Vec<Order>, is_bid: bool, leading_key: i64, trailing_key: i64, insert_run: usize, runs: &mut Vec<RunPlan>) {
+    let mut leading_key = leading_key;
+    for orders in run.chunks(insert_run) {
+        runs.push(RunPlan { orders: orders.to_vec(), is_bid, leading_key, trailing_key, first_order_index: 0, block: 0 });
+        leading_key = orders[orders.len() - 1].index;
+    }
+}
+
+/// Linkage of new orders one `addLimitOrdersBid`/`addLimitOrdersAsk` solution inserts next
+/// to each other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunPlan {
+    /// The new orders in linked list order.
+    pub orders: Vec<Order>,
+    pub is_bid: bool,
+    /// Order the run is linked behind, 0 if it becomes the front of the book.
+    pub leading_key: i64,
+    /// Order the last new order points to, 0 if the run goes at the end.
+    pub trailing_key: i64,
+    /// Head of the side once the run is in.
+    pub first_order_index: i64,
+    /// Block, counted from 0, the run goes in. Runs and parts on the same side go in
+    /// consecutive blocks.
+    pub block: usize,
+}
+
+impl RunPlan {
+    /// The new orders as they are stored on chain, with their indices.
+    pub fn limit_orders(&self) -> Vec<(i64, LimitOrder)> {
+        self.orders
+            .iter()
+            .enumerate()
+            .map(|(i, order)| {
+                let next_key = self.orders.get(i + 1).map_or(self.trailing_key, |next| next.index);
+                let limit_order = LimitOrder {
+                    max_amnt: order.max_amnt,
+                    price: order.price,
+                    is_bid: order.is_bid,
+                    addr: order.addr,
+                    auth: order.auth,
+                    next_key,
+                };
+                (order.index, limit_order)
+            })
+            .collect()
+    }
+
+    pub fn to_solution(&self) -> Result<Solution> {
+        self.to_solution_for(&ContractVariant::standard())
+    }
+
+    /// The solution for a contract deployed as `variant`.
+    pub fn to_solution_for(&self, variant: &ContractVariant) -> Result<Solution> {
+        produce_solution_add_limit_orders_batch(
+            variant,
+            self.is_bid,
+            self.leading_key,
+            self.trailing_key,
+            self.first_order_index,
+            &self.limit_orders(),
+        )
+    }
+}
+
+/// New orders planned by [`OrderBook::plan_ladder`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LadderPlan {
+    /// The orders as they were given, which is their time priority within a level.
+    pub orders: Vec<Order>,
+    pub runs: Vec<RunPlan>,
+    /// Most orders per run the plan was split for.
+    pub insert_run: usize,
+}
+
+impl LadderPlan {
+    /// Whether every order lands in the first block.
+    pub fn is_atomic(&self) -> bool {
+        self.runs.iter().all(|run| run.block == 0)
+    }
+
+    /// One solution set per block, to be built in order. A ladder with at most one run per
+    /// side, none longer than [`INSERT_RUN`], is a single set.
+    pub fn to_solution_sets(&self) -> Result<Vec<SolutionSet>> {
+        self.to_solution_sets_for(&ContractVariant::standard())
+    }
+
+    /// [`Self::to_solution_sets`] for a contract deployed as `variant`, whose runs must be
+    /// as long as the plan's.
+    pub fn to_solution_sets_for(&self, variant: &ContractVariant) -> Result<Vec<SolutionSet>> {
+        if self.insert_run != variant.insert_run {
+            return Err(SolverError::Validation(format!(
+                "ladder is planned for runs of {}, the contract takes {}",
+                self.insert_run, variant.insert_run
+            )));
+        }
+        let blocks = self.runs.iter().map(|run| run.block + 1).max().unwrap_or(0);
+        (0..blocks)
+            .map(|block| {
+                let solutions = self
+                    .runs
+                    .iter()
+                    .filter(|run| run.block == block)
+                    .map(|run| run.to_solution_for(variant))
+                    .collect::<Result<_>>()?;
+                Ok(SolutionSet { solutions })
+            })
+            .collect()
+    }
+
+    /// Adds the orders to the mirror, once all of their blocks have been built.
+    pub fn apply(&self, orderbook: &mut OrderBook) {
+        for order in &self.orders {
+            orderbook.insert(order.clone());
+        }
+    }
+}
+
+/// Linkage fields of a `removeLimitOrderBid`/`removeLimitOrderAsk` solution for one order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CancelPlan {
+    /// The order that was taken out of the mirror.
+    pub order: Order,
+    /// Where the order sat within its price level.
+    pub level_position: usize,
+    /// Order linked in front of the cancelled one, 0 if it was the first order.
+    pub leading_key: i64,
+    /// Order the cancelled one pointed to, 0 if it was the last order.
+    pub trailing_key: i64,
+    pub leading_order_next: i64,
+    pub first_order_index: i64,
+}
+
+impl CancelPlan {
+    pub fn to_solution(&self) -> Solution {
+        let build = if self.order.is_bid {
+            produce_solution_remove_limit_order_bid
+        } else {
+            produce_solution_remove_limit_order_ask
+        };
+        build(
+            self.leading_key,
+            self.trailing_key,
+            self.order.index,
+            self.leading_order_next,
+            self.first_order_index,
+        )
+    }
+
+    /// Puts the order back into the mirror at its old place, for when the block failed.
+    pub fn undo(&self, orderbook: &mut OrderBook) {
+        let level = orderbook.side_mut(self.order.is_bid).entry(self.order.price as u64).or_default();
+        let position = self.level_position.min(level.len());
+        level.insert(position, self.order.clone());
+    }
+}
+
+/// Mirror of the contract's `balances_0`/`balances_1` maps. Missing entries read as 0.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Balances {
+    pub balance_0: HashMap<[Word; 4], i64>,
+    pub balance_1: HashMap<[Word; 4], i64>,
+}
+
+impl Balances {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_0(&self, addr: &[Word; 4]) -> i64 {
+        self.balance_0.get(addr).copied().unwrap_or(0)
+    }
+
+    pub fn get_1(&self, addr: &[Word; 4]) -> i64 {
+        self.balance_1.get(addr).copied().unwrap_or(0)
+    }
+
+    pub fn set(&mut self, addr: [Word; 4], balance_0: i64, balance_1: i64) {
+        self.balance_0.insert(addr, balance_0);
+        self.balance_1.insert(addr, balance_1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(index: i64, price: i64, is_bid: bool) -> Order {
+        Order { index, max_amnt: 10, price, is_bid, addr: [0; 4], auth: [0; 4] }
+    }
+
+    #[test]
+    fn plan_insert_links_in_price_time_order() {
+        let mut orderbook = OrderBook::new();
+        assert_eq!(orderbook.plan_insert(order(1, 100, true)).unwrap().first_order_index, 1);
+        assert!(matches!(orderbook.plan_insert(order(1, 0, true)), Err(SolverError::Validation(_))));
+        for o in [order(1, 100, true), order(2, 98, true), order(3, 100, false), order(4, 102, false)] {
+            orderbook.plan_insert(o).unwrap().apply(&mut orderbook);
+        }
+
+        // a bid at an existing price goes behind that level
+        let plan = orderbook.plan_insert(order(5, 100, true)).unwrap();
+        assert_eq!((plan.leading_key, plan.trailing_key), (1, 2));
+        assert_eq!((plan.leading_order_next, plan.first_order_index), (5, 1));
+
+        // a better bid becomes the new first order
+        let plan = orderbook.plan_insert(order(6, 101, true)).unwrap();
+        assert_eq!((plan.leading_key, plan.trailing_key), (0, 1));
+        assert_eq!((plan.leading_order_next, plan.first_order_index), (0, 6));
+
+        let plan = orderbook.plan_insert(order(7, 101, false)).unwrap();
+        assert_eq!((plan.leading_key, plan.trailing_key), (3, 4));
+        assert_eq!(plan.limit_order().next_key, 4);
+        plan.apply(&mut orderbook);
+        assert_eq!(orderbook.next_index(3, false), 7);
+    }
+
+    #[test]
+    fn plan_ladder_links_runs_between_resting_orders() {
+        let mut orderbook = OrderBook::new();
+        for o in [order(1, 100, true), order(2, 98, true), order(3, 102, false)] {
+            orderbook.insert(o);
+        }
+        let ladder = vec![order(10, 100, true), order(11, 100, true), order(12, 99, true), order(13, 101, true), order(14, 103, false)];
+        let plan = orderbook.plan_ladder(ladder).unwrap();
+        // both bid runs write the head, so the second waits for the first
+        assert!(!plan.is_atomic());
+
+        let runs: Vec<_> = plan
+            .runs
+            .iter()
+            .map(|run| (run.block, run.leading_key, run.orders.iter().map(|o| o.index).collect::<Vec<_>>(), run.trailing_key, run.first_order_index))
+            .collect();
+        assert_eq!(runs, vec![(0, 0, vec![13], 1, 13), (1, 1, vec![10, 11, 12], 2, 13), (0, 3, vec![14], 0, 3)]);
+        assert_eq!(plan.to_solution_sets().unwrap().iter().map(|set| set.solutions.len()).collect::<Vec<_>>(), vec![2, 1]);
+        let next_keys: Vec<_> = plan.runs[1].limit_orders().iter().map(|(_, o)| o.next_key).collect();
+        assert_eq!(next_keys, vec![11, 12, 2]);
+
+        plan.apply(&mut orderbook);
+        let chain: Vec<_> = orderbook.chain(true).iter().map(|o| o.index).collect();
+        assert_eq!(chain, vec![13, 1, 10, 11, 12, 2]);
+
+        assert!(orderbook.plan_ladder(vec![order(15, 100, true), order(15, 99, true)]).is_err());
+        assert!(orderbook.plan_ladder(vec![order(3, 100, true)]).is_err());
+    }
+
+    #[test]
+    fn plan_ladder_splits_long_runs_across_blocks() {
+        let orderbook = OrderBook::new();
+        let plan = orderbook.plan_ladder((1..=7).map(|i| order(i, 100 - i, true)).collect()).unwrap();
+        assert!(!plan.is_atomic());
+        let runs: Vec<_> = plan.runs.iter().map(|run| (run.block, run.leading_key, run.orders.len(), run.trailing_key)).collect();
+        assert_eq!(runs, vec![(0, 0, INSERT_RUN, 0), (1, INSERT_RUN as i64, 2, 0)]);
+
+        // a contract with shorter runs needs a plan split for it
+        let plan = orderbook.plan_ladder_with((1..=3).map(|i| order(i, 100 - i, true)).collect(), 2).unwrap();
+        assert_eq!(plan.runs.iter().map(|run| run.orders.len()).collect::<Vec<_>>(), vec![2, 1]);
+        assert!(matches!(plan.to_solution_sets(), Err(SolverError::Validation(_))));
+        let mut variant = ContractVariant::standard();
+        variant.insert_run = 2;
+        assert_eq!(plan.to_solution_sets_for(&variant).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn plan_cancel_unlinks_and_undo_restores() {
+        let mut orderbook = OrderBook::new();
+        for o in [order(1, 100, true), order(2, 100, true), order(3, 99, true)] {
+            orderbook.insert(o);
+        }
+        let before = orderbook.chain(true).iter().map(|o| o.index).collect::<Vec<_>>();
+
+        let plan = orderbook.plan_cancel(2, true).unwrap();
+        assert_eq!((plan.leading_key, plan.trailing_key), (1, 3));
+        assert_eq!((plan.leading_order_next, plan.first_order_index), (3, 1));
+        assert_eq!(orderbook.next_index(1, true), 3);
+
+        plan.undo(&mut orderbook);
+        assert_eq!(orderbook.chain(true).iter().map(|o| o.index).collect::<Vec<_>>(), before);
+
+        let plan = orderbook.plan_cancel(1, true).unwrap();
+        assert_eq!((plan.leading_key, plan.trailing_key), (0, 2));
+        assert_eq!((plan.leading_order_next, plan.first_order_index), (0, 2));
+        assert!(orderbook.plan_cancel(42, true).is_none());
+        assert!(orderbook.plan_cancel(1, false).is_none());
+    }
+}
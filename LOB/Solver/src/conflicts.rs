@@ -0,0 +1,354 @@
+//! Storage overlaps between solutions meant for the same block.
+//!
+//! Every solution of a solution set is checked against the state from before the set, so
+//! a solution that reads a key another one writes sees the old value, and two solutions
+//! writing one key cannot share a set at all. [`analyze`] works out which keys each
+//! solution reads and writes, reports every write/write and read/write overlap on the
+//! orderbook's `bid_orders`, `ask_orders`, `first_*_order` and `balances_*` keys, and
+//! suggests a partition into blocks that keeps the solutions in order.
+//!
+//! Writes are the solution's state mutations. Reads follow from the predicate and its
+//! `predicate_data`: e.g. `addLimitOrderBid` reads the new slot, the leading order's
+//! `next_key` and `price`, the trailing order's `price`, `first_bid_order` and the owner's
+//! `balances_0`, and `addLimitOrderBidBehind` all of that but `first_bid_order`. Every key
+//! a predicate writes it also reads. Solutions for predicates outside the standard
+//! contract only contribute their writes.
+use crate::diagnostics::{Decoded, predicate_name};
+use crate::error::{Result, SolverError};
+use crate::keys::{balances_0_key, balances_1_key, first_order_key, order_keys};
+use essential_types::{ContentAddress, Key, Word, solution::Solution};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+const ORDER_FIELDS: [&str; 6] = ["max_amnt", "price", "isBid", "addr", "auth", "next_key"];
+
+/// A storage slot of the orderbook contract.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Slot {
+    /// One field of `bid_orders[index]` or `ask_orders[index]`.
+    Order { is_bid: bool, index: i64, field: &'static str },
+    FirstOrder { is_bid: bool },
+    /// `balances_0[addr]` for token 0, `balances_1[addr]` for token 1.
+    Balance { token: u8, addr: [Word; 4] },
+    /// A mutated key that is none of the above.
+    Raw(Key),
+}
+
+impl Slot {
+    fn order(is_bid: bool, index: i64, field: &'static str) -> Self {
+        Slot::Order { is_bid, index, field }
+    }
+
+    fn key(&self) -> Result<Key> {
+        match self {
+            Slot::Order { is_bid, index, field } => {
+                let position = ORDER_FIELDS.iter().position(|f| f == field).unwrap_or_default();
+                Ok(order_keys(*index, *is_bid)?[position].clone())
+            }
+            Slot::FirstOrder { is_bid } => first_order_key(*is_bid),
+            Slot::Balance { token: 0, addr } => balances_0_key(*addr),
+            Slot::Balance { addr, .. } => balances_1_key(*addr),
+            Slot::Raw(key) => Ok(key.clone()),
+        }
+    }
+}
+
+impl fmt::Display for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Slot::Order { is_bid, index, field } => write!(f, "{}_orders[{}].{}", side(*is_bid), index, field),
+            Slot::FirstOrder { is_bid } => write!(f, "first_{}_order", side(*is_bid)),
+            Slot::Balance { token, addr } => write!(f, "balances_{}[{}]", token, Decoded::B256(*addr)),
+            Slot::Raw(key) => write!(f, "key {:?}", key),
+        }
+    }
+}
+
+/// How two solutions touch the same slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OverlapKind {
+    /// Both write it.
+    WriteWrite,
+    /// The earlier solution reads what the later one writes.
+    ReadWrite,
+    /// The earlier solution writes what the later one reads.
+    WriteRead,
+}
+
+/// Two solutions, by their position in the analyzed list, touching one slot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Overlap {
+    pub first: usize,
+    pub second: usize,
+    pub kind: OverlapKind,
+    pub slot: Slot,
+}
+
+impl fmt::Display for Overlap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (first, second) = (self.first, self.second);
+        match self.kind {
+            OverlapKind::WriteWrite => write!(f, "write/write: #{} and #{} both write {}", first, second, self.slot),
+            OverlapKind::ReadWrite => write!(f, "read/write: #{} reads {} which #{} writes", first, self.slot, second),
+            OverlapKind::WriteRead => write!(f, "read/write: #{} writes {} which #{} reads", first, self.slot, second),
+        }
+    }
+}
+
+/// The overlaps found in a list of solutions and a block partition that avoids them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConflictReport {
+    pub overlaps: Vec<Overlap>,
+    /// Positions of the solutions to put in each block, in order. Solutions keep their
+    /// relative order and no two solutions of a block overlap.
+    pub blocks: Vec<Vec<usize>>,
+}
+
+impl ConflictReport {
+    pub fn is_conflict_free(&self) -> bool {
+        self.overlaps.is_empty()
+    }
+}
+
+impl fmt::Display for ConflictReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.overlaps.is_empty() {
+            writeln!(f, "no overlapping storage keys")?;
+        }
+        for overlap in &self.overlaps {
+            writeln!(f, "{}", overlap)?;
+        }
+        for (i, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "block {}: {:?}", i + 1, block)?;
+        }
+        Ok(())
+    }
+}
+
+/// Keys one solution reads and writes, qualified by contract.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Footprint {
+    pub reads: HashSet<(ContentAddress, Key)>,
+    pub writes: HashSet<(ContentAddress, Key)>,
+}
+
+impl Footprint {
+    /// Whether this footprint and `other` touch a common key with at least one write.
+    pub fn overlaps(&self, other: &Footprint) -> bool {
+        !self.writes.is_disjoint(&other.writes) || !self.reads.is_disjoint(&other.writes) || !self.writes.is_disjoint(&other.reads)
+    }
+
+    pub(crate) fn extend(&mut self, other: &Footprint) {
+        self.reads.extend(other.reads.iter().cloned());
+        self.writes.extend(other.writes.iter().cloned());
+    }
+}
+
+/// The keys `solution` reads and writes.
+pub fn footprint(solution: &Solution) -> Result<Footprint> {
+    Ok(footprint_with_slots(solution)?.0)
+}
+
+/// Reports every overlap between `solutions` and suggests a block partition.
+pub fn analyze(solutions: &[Solution]) -> Result<ConflictReport> {
+    let mut names: HashMap<Key, Slot> = HashMap::new();
+    let mut footprints = vec![];
+    for solution in solutions {
+        let (footprint, slots) = footprint_with_slots(solution)?;
+        names.extend(slots);
+        footprints.push(footprint);
+    }
+    let name = |key: &Key| names.get(key).cloned().unwrap_or_else(|| Slot::Raw(key.clone()));
+
+    let mut overlaps = vec![];
+    for (first, a) in footprints.iter().enumerate() {
+        for (second, b) in footprints.iter().enumerate().skip(first + 1) {
+            let mut found: Vec<Overlap> = vec![];
+            let mut push = |kind, (_, key): &(ContentAddress, Key)| found.push(Overlap { first, second, kind, slot: name(key) });
+            a.writes.intersection(&b.writes).for_each(|key| push(OverlapKind::WriteWrite, key));
+            a.reads.difference(&a.writes).filter(|key| b.writes.contains(key)).for_each(|key| push(OverlapKind::ReadWrite, key));
+            a.writes.difference(&b.writes).filter(|key| b.reads.contains(key)).for_each(|key| push(OverlapKind::WriteRead, key));
+            found.sort_by_key(|overlap| overlap.slot.to_string());
+            overlaps.extend(found);
+        }
+    }
+
+    // start a new block whenever the next solution overlaps the open one
+    let mut blocks: Vec<Vec<usize>> = vec![];
+    let mut open = Footprint::default();
+    for (i, footprint) in footprints.iter().enumerate() {
+        if blocks.is_empty() || open.overlaps(footprint) {
+            blocks.push(vec![]);
+            open = Footprint::default();
+        }
+        blocks.last_mut().unwrap().push(i);
+        open.extend(footprint);
+    }
+    Ok(ConflictReport { overlaps, blocks })
+}
+
+// The footprint, and the keys of the slots read by name.
+fn footprint_with_slots(solution: &Solution) -> Result<(Footprint, Vec<(Key, Slot)>)> {
+    let contract = &solution.predicate_to_solve.contract;
+    let writes: HashSet<_> = solution.state_mutations.iter().map(|m| (contract.clone(), m.key.clone())).collect();
+    let slots = slots_read(solution)?
+        .into_iter()
+        .map(|slot| Ok((slot.key()?, slot)))
+        .collect::<Result<Vec<_>>>()?;
+    let mut reads: HashSet<_> = slots.iter().map(|(key, _)| (contract.clone(), key.clone())).collect();
+    reads.extend(writes.iter().cloned());
+    Ok((Footprint { reads, writes }, slots))
+}
+
+// The slots the predicate reads, decoded from the solution's predicate data.
+fn slots_read(solution: &Solution) -> Result<Vec<Slot>> {
+    let data = Data { vars: &solution.predicate_data };
+    let mut slots = vec![];
+    match predicate_name(&solution.predicate_to_solve) {
+        "deposit" | "withdraw" => {
+            let addr = data.b256(2, 0)?;
+            slots.push(Slot::Balance { token: 0, addr });
+            slots.push(Slot::Balance { token: 1, addr });
+        }
+        name @ ("addLimitOrderBid" | "addLimitOrderAsk" | "addLimitOrderBidBehind" | "addLimitOrderAskBehind") => {
+            let is_bid = name.starts_with("addLimitOrderBid");
+            let (leading, trailing, new_index) = (data.int(0, 0)?, data.int(1, 0)?, data.int(3, 0)?);
+            slots.extend(ORDER_FIELDS.map(|field| Slot::order(is_bid, new_index, field)));
+            slots.push(Slot::order(is_bid, leading, "next_key"));
+            slots.push(Slot::order(is_bid, leading, "price"));
+            slots.push(Slot::order(is_bid, trailing, "price"));
+            // an add behind another order never touches the head
+            if !name.ends_with("Behind") {
+                slots.push(Slot::FirstOrder { is_bid });
+            }
+            // bids lock token 0, asks token 1
+            slots.push(Slot::Balance { token: if is_bid { 0 } else { 1 }, addr: data.b256(2, 3)? });
+        }
+        name @ ("addLimitOrdersBid" | "addLimitOrdersAsk") => {
+            let is_bid = name == "addLimitOrdersBid";
+            let (leading, trailing) = (data.int(0, 0)?, data.int(1, 0)?);
+            slots.push(Slot::order(is_bid, leading, "next_key"));
+            slots.push(Slot::order(is_bid, leading, "price"));
+            slots.push(Slot::order(is_bid, trailing, "price"));
+            slots.push(Slot::FirstOrder { is_bid });
+            // add_order is the order's 12 words followed by its index, 0 for padding
+            for i in 0..data.len(2) / 13 {
+                let index = data.int(2, i * 13 + 12)?;
+                if index != 0 {
+                    slots.extend(ORDER_FIELDS.map(|field| Slot::order(is_bid, index, field)));
+                    slots.push(Slot::Balance { token: if is_bid { 0 } else { 1 }, addr: data.b256(2, i * 13 + 3)? });
+                }
+            }
+        }
+        name @ ("removeLimitOrderBid" | "removeLimitOrderAsk") => {
+            let is_bid = name == "removeLimitOrderBid";
+            let (leading, trailing, middle) = (data.int(0, 0)?, data.int(1, 0)?, data.int(2, 0)?);
+            slots.extend(ORDER_FIELDS.map(|field| Slot::order(is_bid, middle, field)));
+            slots.push(Slot::order(is_bid, leading, "next_key"));
+            slots.push(Slot::order(is_bid, leading, "price"));
+            slots.push(Slot::order(is_bid, trailing, "price"));
+            slots.push(Slot::FirstOrder { is_bid });
+        }
+        "settle" => {
+            settled_orders(&data, 4, true, &mut slots)?;
+            settled_orders(&data, 5, false, &mut slots)?;
+            solver_balances(&data, 6, &mut slots)?;
+        }
+        "settleMarketOrders" => {
+            for var in [4, 5] {
+                for i in 0..data.len(var) / 9 {
+                    let addr = data.b256(var, i * 9 + 1)?;
+                    slots.push(Slot::Balance { token: 0, addr });
+                    slots.push(Slot::Balance { token: 1, addr });
+                }
+            }
+            settled_orders(&data, 6, true, &mut slots)?;
+            settled_orders(&data, 7, false, &mut slots)?;
+            solver_balances(&data, 10, &mut slots)?;
+        }
+        _ => {}
+    }
+    Ok(slots)
+}
+
+// The `settle_order` array in `var`: every field of each referenced order and the head.
+fn settled_orders(data: &Data, var: usize, is_bid: bool, slots: &mut Vec<Slot>) -> Result<()> {
+    for i in 0..data.len(var) / 5 {
+        let index = data.int(var, i * 5)?;
+        if index != 0 {
+            slots.extend(ORDER_FIELDS.map(|field| Slot::order(is_bid, index, field)));
+        }
+    }
+    slots.push(Slot::FirstOrder { is_bid });
+    Ok(())
+}
+
+// Both balances of the owner of `solver_orders[0]`, the `order[2]` in `var`.
+fn solver_balances(data: &Data, var: usize, slots: &mut Vec<Slot>) -> Result<()> {
+    let addr = data.b256(var, 3)?;
+    slots.push(Slot::Balance { token: 0, addr });
+    slots.push(Slot::Balance { token: 1, addr });
+    Ok(())
+}
+
+// Predicate data with tuples and arrays flattened into words, one value per var.
+struct Data<'a> {
+    vars: &'a [Vec<Word>],
+}
+
+impl Data<'_> {
+    fn len(&self, var: usize) -> usize {
+        self.vars.get(var).map_or(0, Vec::len)
+    }
+
+    fn int(&self, var: usize, at: usize) -> Result<Word> {
+        self.vars
+            .get(var)
+            .and_then(|words| words.get(at))
+            .copied()
+            .ok_or_else(|| SolverError::Decode(format!("predicate data var {} has no word {}", var, at)))
+    }
+
+    fn b256(&self, var: usize, at: usize) -> Result<[Word; 4]> {
+        Ok([self.int(var, at)?, self.int(var, at + 1)?, self.int(var, at + 2)?, self.int(var, at + 3)?])
+    }
+}
+
+fn side(is_bid: bool) -> &'static str {
+    if is_bid { "bid" } else { "ask" }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solutions::{LimitOrder, produce_solution_add_limit_order_ask, produce_solution_add_limit_order_bid, produce_solution_deposit};
+
+    fn order(addr: i64, is_bid: bool) -> LimitOrder {
+        LimitOrder { max_amnt: 1, price: 100, is_bid, addr: [addr; 4], auth: [addr; 4], next_key: 0 }
+    }
+
+    #[test]
+    fn head_adds_overlap_on_the_first_order() {
+        let bid = produce_solution_add_limit_order_bid(0, 0, order(1, true), 10, 0, 10);
+        let other_bid = produce_solution_add_limit_order_bid(0, 0, order(2, true), 11, 0, 11);
+        let ask = produce_solution_add_limit_order_ask(0, 0, order(3, false), 10, 0, 10);
+
+        let report = analyze(&[bid.clone(), ask, other_bid]).unwrap();
+        assert!(report.overlaps.iter().all(|o| (o.first, o.second) == (0, 2)), "{}", report);
+        let first_bid = Slot::FirstOrder { is_bid: true };
+        assert!(report.overlaps.contains(&Overlap { first: 0, second: 2, kind: OverlapKind::WriteWrite, slot: first_bid }));
+        assert_eq!(report.blocks, vec![vec![0, 1], vec![2]]);
+    }
+
+    #[test]
+    fn add_reads_the_balance_a_deposit_writes() {
+        let deposit = produce_solution_deposit(100, 100, 0, 0, [1; 4], [1; 4], [1; 4]);
+        let bid = produce_solution_add_limit_order_bid(5, 0, order(1, true), 10, 10, 0);
+
+        let report = analyze(&[deposit, bid]).unwrap();
+        let balance = Slot::Balance { token: 0, addr: [1; 4] };
+        assert_eq!(report.overlaps, vec![Overlap { first: 0, second: 1, kind: OverlapKind::WriteRead, slot: balance }]);
+        assert_eq!(report.blocks, vec![vec![0], vec![1]]);
+        assert!(report.to_string().contains("#0 writes balances_0["), "{}", report);
+    }
+}
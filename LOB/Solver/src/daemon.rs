@@ -0,0 +1,516 @@
+// Long-running solver process.
+//
+// The daemon compiles and deploys the orderbook contract into a local node and reads the
+// resting orders back from its storage, then loops: every tick it pulls new intents
+// (deposits, limit orders, market orders) from the intake file, submits them, settles
+// whatever has crossed and clears queued market orders.
+// The in-memory `OrderBook` and balance maps are only updated once the block containing
+// the corresponding solution has been built without failures, so the mirror never runs
+// ahead of the chain.
+use essential_app_utils as utils;
+use essential_app_utils::compile::compile_pint_project;
+use essential_node_types::BigBang;
+use essential_types::{ContentAddress, Word, contract::Contract, Program, solution::{Solution, SolutionSet}};
+use orderbook_solver::book::{Balances, Order, OrderBook};
+use orderbook_solver::error::{Result, SolverError};
+use orderbook_solver::fixed::add;
+use orderbook_solver::ids::{IdScheme, OrderIdAllocator};
+use orderbook_solver::keys::hex_to_i64_array;
+use orderbook_solver::loader::load_orderbook;
+use orderbook_solver::market::MarketQueue;
+use orderbook_solver::matching::match_book;
+use orderbook_solver::node;
+use orderbook_solver::preflight::{Violation, check_market_settle, check_settle};
+use orderbook_solver::reconcile::reconcile;
+use orderbook_solver::settlement::{SETTLE_BATCH, plan_settle_with};
+use orderbook_solver::strategy::{FixedSpread, Mid, ProfitMax, SolverStrategy, Vwap};
+use orderbook_solver::state::StateReader;
+use orderbook_solver::solutions::*;
+use orderbook_solver::variant::{self, ContractVariant};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::io::SeekFrom;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tracing::{error, info, warn};
+
+// Config file format (JSON):
+// {
+//     "contract_path": "../PintLOB/orderbook",
+//     "solver_address": "0x...",
+//     "solver_deposit_0": 1000000,
+//     "solver_deposit_1": 1000000,
+//     "intake_path": "intents.jsonl",
+//     "tick_ms": 500,
+//     "reconcile_every": 20,
+//     "strategy": "vwap",
+//     "batch_size": 10,
+//     "order_ids": "monotonic"
+// }
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    pub contract_path: PathBuf,
+    pub solver_address: String,
+    #[serde(default)]
+    pub solver_deposit_0: i64,
+    #[serde(default)]
+    pub solver_deposit_1: i64,
+    pub intake_path: Option<PathBuf>,
+    #[serde(default = "default_tick_ms")]
+    pub tick_ms: u64,
+    /// Compare the mirror with contract state every this many ticks, 0 to never do it.
+    #[serde(default)]
+    pub reconcile_every: u64,
+    /// How the solver prices its orders in `settle`: "vwap", "mid", "profit_max" or
+    /// {"fixed_spread": {"spread": 20}}.
+    #[serde(default)]
+    pub strategy: StrategyConfig,
+    /// Orders per side in `settle` and `settleMarketOrders`. Anything but 10 deploys a
+    /// variant of the contract rendered into a sibling directory of `contract_path`.
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    /// How new limit orders get their index: "monotonic", "hashed" or
+    /// {"deterministic": {"seed": 7}}.
+    #[serde(default)]
+    pub order_ids: IdScheme,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StrategyConfig {
+    #[default]
+    Vwap,
+    Mid,
+    FixedSpread { spread: i64 },
+    ProfitMax,
+}
+
+impl StrategyConfig {
+    fn build(self) -> Box<dyn SolverStrategy> {
+        match self {
+            StrategyConfig::Vwap => Box::new(Vwap),
+            StrategyConfig::Mid => Box::new(Mid),
+            StrategyConfig::FixedSpread { spread } => Box::new(FixedSpread { spread }),
+            StrategyConfig::ProfitMax => Box::new(ProfitMax),
+        }
+    }
+}
+
+fn default_tick_ms() -> u64 {
+    500
+}
+
+fn default_batch_size() -> usize {
+    SETTLE_BATCH
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config> {
+        let raw = std::fs::read_to_string(path)?;
+        let mut config: Config = serde_json::from_str(&raw)
+            .map_err(|e| SolverError::Decode(format!("invalid config file {:?}: {}", path, e)))?;
+        // relative paths are resolved against the directory holding the config file
+        let base = path.parent().unwrap_or(Path::new("."));
+        if config.contract_path.is_relative() {
+            config.contract_path = base.join(&config.contract_path);
+        }
+        if let Some(intake) = &config.intake_path
+            && intake.is_relative()
+        {
+            config.intake_path = Some(base.join(intake));
+        }
+        Ok(config)
+    }
+}
+
+// One line of the intake file. Every line is a self-contained JSON object, e.g.
+// {"type":"limit","side":"bid","price":100,"amount":10,"addr":"0x..","auth":"0x.."}
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Intent {
+    Deposit { addr: String, amount0: i64, amount1: i64 },
+    Limit { side: Side, price: i64, amount: i64, addr: String, auth: String },
+    Market { side: Side, amount: i64, addr: String, auth: String },
+    Cancel { side: Side, index: i64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Side {
+    Bid,
+    Ask,
+}
+
+struct Solver {
+    config: Config,
+    dbs: utils::db::Dbs,
+    contract: ContentAddress,
+    variant: ContractVariant,
+    solver_addr: [Word; 4],
+    orderbook: OrderBook,
+    balances: Balances,
+    market_orders: MarketQueue,
+    intake_offset: u64,
+    ids: OrderIdAllocator,
+    blocks: u64,
+    ticks: u64,
+}
+
+pub async fn run(config: Config) -> Result<()> {
+    let mut solver = Solver::deploy(config).await?;
+    let tick = Duration::from_millis(solver.config.tick_ms);
+
+    #[cfg(unix)]
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+
+    loop {
+        #[cfg(unix)]
+        let shutdown = async {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {},
+                _ = terminate.recv() => {},
+            }
+        };
+        #[cfg(not(unix))]
+        let shutdown = async {
+            let _ = tokio::signal::ctrl_c().await;
+        };
+
+        tokio::select! {
+            _ = shutdown => break,
+            _ = tokio::time::sleep(tick) => solver.tick().await,
+        }
+    }
+
+    // a tick is never interrupted half way, so the mirror is consistent with the chain here
+    info!(
+        "solver shutting down after {} blocks: {} bid levels, {} ask levels, {} queued market orders",
+        solver.blocks,
+        solver.orderbook.bids.len(),
+        solver.orderbook.asks.len(),
+        solver.market_orders.len(),
+    );
+    Ok(())
+}
+
+impl Solver {
+    async fn deploy(config: Config) -> Result<Solver> {
+        let project = if config.batch_size == SETTLE_BATCH {
+            config.contract_path.clone()
+        } else {
+            variant::write_project(&config.contract_path, config.batch_size, INSERT_RUN)?
+        };
+        let (orderbook, programs): (Contract, Vec<Program>) =
+            compile_pint_project(project.clone())
+                .await
+                .map_err(|e| SolverError::Deploy(e.to_string()))?;
+        let contract_address = essential_hash::contract_addr::from_contract(&orderbook);
+        // read back from the build, so failed solutions are decoded with the deployed ABI
+        let variant = ContractVariant::from_project(&project, &orderbook, config.batch_size)?;
+        info!("deployed orderbook contract: {:?}", contract_address);
+
+        let dbs = utils::db::new_dbs().await;
+        let big_bang = BigBang::default();
+        essential_app_utils::deploy::register_contract_and_programs(
+            &dbs.builder,
+            &big_bang.contract_registry,
+            &big_bang.program_registry,
+            &orderbook,
+            programs,
+        )
+        .await
+        .map_err(|e| SolverError::Deploy(e.to_string()))?;
+
+        let solver_addr = hex_to_i64_array(&config.solver_address)?;
+        let ids = OrderIdAllocator::new(config.order_ids);
+        let mut solver = Solver {
+            config,
+            dbs,
+            contract: contract_address,
+            market_orders: MarketQueue::with_batch(variant.batch),
+            variant,
+            solver_addr,
+            orderbook: OrderBook::new(),
+            balances: Balances::new(),
+            intake_offset: 0,
+            ids,
+            blocks: 0,
+            ticks: 0,
+        };
+
+        // after a restart the book is whatever the chain holds, not an empty mirror
+        let state = StateReader::new(&solver.dbs, solver.contract.clone());
+        solver.orderbook = load_orderbook(&state).await?;
+        info!(
+            "loaded {} resting bids and {} resting asks from chain",
+            solver.orderbook.chain(true).len(),
+            solver.orderbook.chain(false).len(),
+        );
+
+        // the solver takes the other side of every settlement, so it needs inventory up front
+        let (amount0, amount1) = (solver.config.solver_deposit_0, solver.config.solver_deposit_1);
+        if amount0 != 0 || amount1 != 0 {
+            solver.deposit(solver_addr, amount0, amount1).await?;
+        }
+        Ok(solver)
+    }
+
+    async fn tick(&mut self) {
+        for intent in self.read_intake().await {
+            if let Err(e) = self.handle(intent.clone()).await {
+                warn!("dropping intent {:?}: {}", intent, e);
+            }
+        }
+        self.settle_crossing().await;
+        self.settle_market_orders().await;
+
+        self.ticks += 1;
+        if self.config.reconcile_every != 0 && self.ticks.is_multiple_of(self.config.reconcile_every) {
+            let state = StateReader::new(&self.dbs, self.contract.clone());
+            match reconcile(&state, &self.orderbook, &self.balances).await {
+                Ok(report) if report.is_consistent() => {}
+                Ok(report) => warn!("{}", report),
+                Err(e) => error!("reconcile failed: {}", e),
+            }
+        }
+    }
+
+    async fn handle(&mut self, intent: Intent) -> Result<()> {
+        match intent {
+            Intent::Deposit { addr, amount0, amount1 } => {
+                self.deposit(hex_to_i64_array(&addr)?, amount0, amount1).await?;
+            }
+            Intent::Limit { side, price, amount, addr, auth } => {
+                if price <= 0 || amount <= 0 {
+                    return Err(SolverError::Validation(format!("limit order of {} at {}", amount, price)));
+                }
+                let addr = hex_to_i64_array(&addr)?;
+                let state = StateReader::new(&self.dbs, self.contract.clone());
+                let order = Order {
+                    index: self.ids.next_free(&self.orderbook, &state, addr).await?,
+                    max_amnt: amount,
+                    price,
+                    is_bid: side == Side::Bid,
+                    addr,
+                    auth: hex_to_i64_array(&auth)?,
+                };
+                self.add_limit_order(order).await?;
+            }
+            Intent::Market { side, amount, addr, auth } => {
+                if amount <= 0 {
+                    return Err(SolverError::Validation(format!("market order of {}", amount)));
+                }
+                let order = MarketOrder {
+                    amount,
+                    addr: hex_to_i64_array(&addr)?,
+                    auth: hex_to_i64_array(&auth)?,
+                };
+                self.market_orders.push(order, side == Side::Bid);
+            }
+            Intent::Cancel { side, index } => self.cancel_order(index, side == Side::Bid).await,
+        }
+        Ok(())
+    }
+
+    // Reads every complete line appended to the intake file since the last tick.
+    async fn read_intake(&mut self) -> Vec<Intent> {
+        let Some(path) = &self.config.intake_path else {
+            return vec![];
+        };
+        let Ok(mut file) = tokio::fs::File::open(path).await else {
+            return vec![];
+        };
+        let mut content = String::new();
+        if file.seek(SeekFrom::Start(self.intake_offset)).await.is_err() || file.read_to_string(&mut content).await.is_err() {
+            return vec![];
+        }
+        // a line still being written is picked up on a later tick
+        let Some(end) = content.rfind('\n') else {
+            return vec![];
+        };
+        let mut intents = vec![];
+        for line in content[..end].lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<Intent>(line) {
+                Ok(intent) => intents.push(intent),
+                Err(e) => warn!("skipping malformed intent {:?}: {}", line, e),
+            }
+        }
+        self.intake_offset += end as u64 + 1;
+        intents
+    }
+
+    // Submits one solution set and builds a block from it. Returns true if nothing failed.
+    // Errors are logged rather than returned: a rejected solution must not stop the daemon.
+    // `intent` names what the solutions are for in the failure report.
+    async fn submit_and_build(&mut self, intent: &str, solutions: Vec<Solution>) -> bool {
+        // the builders address the standard contract, point them at the deployed one
+        let solutions = match solutions.into_iter().map(|s| self.variant.retarget(s)).collect::<Result<Vec<_>>>() {
+            Ok(solutions) => solutions,
+            Err(e) => {
+                error!("cannot submit {}: {}", intent, e);
+                return false;
+            }
+        };
+        let solution_set = SolutionSet { solutions };
+        let result = node::submit_intent(&self.dbs, &self.variant, intent, solution_set).await;
+        self.blocks += 1;
+        match result {
+            Ok(()) => true,
+            Err(e) => {
+                error!("{}", e);
+                false
+            }
+        }
+    }
+
+    // Rejects negative amounts and balances that would overflow before building anything.
+    async fn deposit(&mut self, addr: [Word; 4], amount0: i64, amount1: i64) -> Result<()> {
+        if amount0 < 0 || amount1 < 0 || (amount0 == 0 && amount1 == 0) {
+            return Err(SolverError::Validation(format!("deposit of ({}, {})", amount0, amount1)));
+        }
+        let final0 = add(self.balances.get_0(&addr), amount0)?;
+        let final1 = add(self.balances.get_1(&addr), amount1)?;
+        let solution = produce_solution_deposit(amount0, final0, amount1, final1, addr, addr, addr);
+        let intent = format!("deposit of ({}, {}) for {:?}", amount0, amount1, addr);
+        if self.submit_and_build(&intent, vec![solution]).await {
+            self.balances.set(addr, final0, final1);
+        }
+        Ok(())
+    }
+
+    // Inserts the order behind every resting order with the same or a better price.
+    async fn add_limit_order(&mut self, order: Order) -> Result<()> {
+        let index = order.index;
+        let plan = match self.orderbook.plan_insert(order) {
+            Ok(plan) => plan,
+            Err(e) => {
+                self.ids.release(index);
+                return Err(e);
+            }
+        };
+        let intent = format!("limit order {} of {} at {}", plan.order.index, plan.order.max_amnt, plan.order.price);
+        if self.submit_and_build(&intent, vec![plan.to_solution()]).await {
+            plan.apply(&mut self.orderbook);
+            info!("resting order {} added", plan.order.index);
+        } else {
+            self.ids.release(plan.order.index);
+        }
+        Ok(())
+    }
+
+    async fn cancel_order(&mut self, index: i64, is_bid: bool) {
+        let Some(plan) = self.orderbook.plan_cancel(index, is_bid) else {
+            warn!("no resting {} {}", if is_bid { "bid" } else { "ask" }, index);
+            return;
+        };
+        if self.submit_and_build(&format!("cancel of order {}", index), vec![plan.to_solution()]).await {
+            self.ids.release(index);
+        } else {
+            plan.undo(&mut self.orderbook);
+        }
+    }
+
+    // Settles the overlapping part of the book in price-time priority. The solver is the
+    // counterparty of each fill: it buys all settled asks and sells to all settled bids.
+    async fn settle_crossing(&mut self) {
+        let report = match_book(&self.orderbook);
+        if report.is_empty() {
+            return;
+        }
+        let strategy = self.config.strategy.build();
+        let plan = match plan_settle_with(
+            &self.orderbook,
+            &self.balances,
+            &report.bids,
+            &report.asks,
+            self.solver_addr,
+            strategy.as_ref(),
+            self.variant.batch,
+        ) {
+            Ok(plan) => plan,
+            Err(e) => {
+                error!("cannot settle crossing orders: {}", e);
+                return;
+            }
+        };
+        for (i, chunk) in plan.chunks.iter().enumerate() {
+            let violations = check_settle(&self.orderbook, &self.balances, chunk);
+            if !violations.is_empty() {
+                log_violations("settle", &violations);
+                return;
+            }
+            let intent = format!("settle chunk {} of {}", i + 1, plan.chunks.len());
+            let solution = match chunk.to_solution_for(&self.variant) {
+                Ok(solution) => solution,
+                Err(e) => {
+                    error!("cannot build {}: {}", intent, e);
+                    return;
+                }
+            };
+            if !self.submit_and_build(&intent, vec![solution]).await {
+                // leave the rest of the book untouched, it will be retried on the next tick
+                return;
+            }
+            chunk.apply(&mut self.orderbook, &mut self.balances);
+            // orders settled in full leave their slots cleared
+            let settled = chunk.bids.iter().chain(&chunk.asks).map(|leg| leg.order.index);
+            let state = StateReader::new(&self.dbs, self.contract.clone());
+            if let Err(e) = self.ids.reclaim(&self.orderbook, &state, settled).await {
+                warn!("cannot reclaim settled order indices: {}", e);
+            }
+        }
+    }
+
+    // Clears queued market orders against the front of the book, pro rata when the queue
+    // wants more than the first batch of resting orders of a side hold. Both sides need at least
+    // one market order, otherwise the contract's average price checks divide by zero.
+    async fn settle_market_orders(&mut self) {
+        let plan = match self.market_orders.plan(&self.orderbook, &self.balances, self.solver_addr) {
+            Ok(Some(plan)) => plan,
+            // nothing to trade on one of the sides yet
+            Ok(None) => return,
+            Err(e) => {
+                error!("cannot settle market orders, dropping the queued market orders: {}", e);
+                self.market_orders.clear();
+                return;
+            }
+        };
+        let violations = check_market_settle(&self.orderbook, &self.balances, &plan.chunk);
+        if !violations.is_empty() {
+            log_violations("settleMarketOrders", &violations);
+            return;
+        }
+        let intent = format!("{} market bids and {} market asks", plan.bids.len(), plan.asks.len());
+        let solution = match plan.chunk.to_solution_for(&self.variant) {
+            Ok(solution) => solution,
+            Err(e) => {
+                error!("cannot build {}: {}", intent, e);
+                return;
+            }
+        };
+        if !self.submit_and_build(&intent, vec![solution]).await {
+            return;
+        }
+
+        if let Err(e) = plan.apply(&mut self.market_orders, &mut self.orderbook, &mut self.balances) {
+            error!("dropping the queued market orders: {}", e);
+            self.market_orders.clear();
+        }
+        let settled = plan.chunk.bids.iter().chain(&plan.chunk.asks).map(|leg| leg.order.index);
+        let state = StateReader::new(&self.dbs, self.contract.clone());
+        if let Err(e) = self.ids.reclaim(&self.orderbook, &state, settled).await {
+            warn!("cannot reclaim settled order indices: {}", e);
+        }
+    }
+}
+
+fn log_violations(predicate: &str, violations: &[Violation]) {
+    warn!("{} solution fails preflight, not submitting it:", predicate);
+    for violation in violations {
+        warn!("  {}", violation);
+    }
+}
@@ -0,0 +1,346 @@
+//! Readable reports for solution sets that failed to make it into a block.
+//!
+//! The builder only reports the content address of a failed solution set and the reason it
+//! failed. [`SubmissionLog`] remembers which solver intent produced each set it submitted,
+//! so a failure can be traced back to it. Every solution in the set is then named after
+//! its predicate in the deployed contract and has its `predicate_data` decoded with that
+//! contract's ABI, so a failed `settle` shows the orders, indices and amounts it was built
+//! from.
+use crate::abi::{addLimitOrderAsk, addLimitOrderAskBehind, addLimitOrderBid, addLimitOrderBidBehind, addLimitOrdersAsk, addLimitOrdersBid, deposit, removeLimitOrderAsk, removeLimitOrderBid, settle, settleMarketOrders, withdraw};
+use crate::variant::ContractVariant;
+use essential_builder::error::InvalidSet;
+use essential_types::{ContentAddress, PredicateAddress, Value, Word, solution::{Solution, SolutionSet}};
+use pint_abi::types::TypeABI;
+use std::collections::HashMap;
+use std::fmt;
+
+/// What the solver meant to do with one solution set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Submission {
+    pub intent: String,
+    pub solutions: Vec<Solution>,
+}
+
+/// Solution sets handed to the builder, by content address.
+#[derive(Debug, Clone)]
+pub struct SubmissionLog {
+    /// The deployed contract the sets were built for.
+    contract: ContractVariant,
+    submissions: HashMap<ContentAddress, Submission>,
+}
+
+impl Default for SubmissionLog {
+    fn default() -> Self {
+        Self::new(ContractVariant::standard())
+    }
+}
+
+impl SubmissionLog {
+    /// A log for solution sets built for `contract`. Its ABI, if it has one, decodes their
+    /// predicate data.
+    pub fn new(contract: ContractVariant) -> Self {
+        Self { contract, submissions: HashMap::new() }
+    }
+
+    /// Remembers `solution_set` as produced by `intent` and returns the address the builder
+    /// will report it under.
+    pub fn record(&mut self, intent: impl Into<String>, solution_set: &SolutionSet) -> ContentAddress {
+        let address = essential_hash::content_addr(solution_set);
+        let submission = Submission { intent: intent.into(), solutions: solution_set.solutions.clone() };
+        self.submissions.insert(address.clone(), submission);
+        address
+    }
+
+    pub fn get(&self, address: &ContentAddress) -> Option<&Submission> {
+        self.submissions.get(address)
+    }
+
+    /// Drops sets that made it into a block, or that are no longer of interest.
+    pub fn forget(&mut self, address: &ContentAddress) {
+        self.submissions.remove(address);
+    }
+
+    /// One report per entry of the builder's `failed` list.
+    pub fn explain(&self, failed: &[(ContentAddress, u32, InvalidSet)]) -> Vec<FailedSet> {
+        failed
+            .iter()
+            .map(|(address, index, reason)| {
+                let submission = self.get(address);
+                FailedSet {
+                    address: address.clone(),
+                    index: *index,
+                    intent: submission.map(|s| s.intent.clone()),
+                    reason: reason.to_string(),
+                    solutions: submission.map_or(vec![], |s| {
+                        s.solutions.iter().map(|solution| describe(solution, &self.contract)).collect()
+                    }),
+                }
+            })
+            .collect()
+    }
+}
+
+/// A failed solution set, with everything known about it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailedSet {
+    pub address: ContentAddress,
+    /// Position of the set among the solution sets of the block.
+    pub index: u32,
+    /// `None` if the set was not submitted through the log.
+    pub intent: Option<String>,
+    pub reason: String,
+    pub solutions: Vec<SolutionReport>,
+}
+
+impl fmt::Display for FailedSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let intent = self.intent.as_deref().unwrap_or("unknown intent");
+        writeln!(f, "solution set {} #{} ({}) failed: {}", self.address, self.index, intent, self.reason)?;
+        for (i, solution) in self.solutions.iter().enumerate() {
+            write!(f, "  solution {}: {}", i, solution)?;
+        }
+        Ok(())
+    }
+}
+
+/// One solution, decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SolutionReport {
+    pub predicate: String,
+    pub vars: Vec<(String, Decoded)>,
+    pub mutations: usize,
+}
+
+impl fmt::Display for SolutionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} with {} state mutations", self.predicate, self.mutations)?;
+        for (name, value) in &self.vars {
+            writeln!(f, "    {} = {}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// A predicate data value laid out according to its ABI type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decoded {
+    Int(Word),
+    Bool(bool),
+    B256([Word; 4]),
+    Tuple(Vec<(Option<String>, Decoded)>),
+    Array(Vec<Decoded>),
+    /// Words that could not be matched to the ABI type.
+    Raw(Vec<Word>),
+}
+
+impl Decoded {
+    fn is_zero(&self) -> bool {
+        match self {
+            Decoded::Int(w) => *w == 0,
+            Decoded::Bool(b) => !b,
+            Decoded::B256(words) => words.iter().all(|w| *w == 0),
+            Decoded::Tuple(fields) => fields.iter().all(|(_, v)| v.is_zero()),
+            Decoded::Array(items) => items.iter().all(Decoded::is_zero),
+            Decoded::Raw(words) => words.iter().all(|w| *w == 0),
+        }
+    }
+}
+
+impl fmt::Display for Decoded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Decoded::Int(w) => write!(f, "{}", w),
+            Decoded::Bool(b) => write!(f, "{}", b),
+            Decoded::B256(words) => {
+                write!(f, "0x")?;
+                for w in words {
+                    write!(f, "{:016X}", w)?;
+                }
+                Ok(())
+            }
+            Decoded::Tuple(fields) => {
+                write!(f, "{{")?;
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { ", " };
+                    match name {
+                        Some(name) => write!(f, "{}{}: {}", sep, name, value)?,
+                        None => write!(f, "{}{}", sep, value)?,
+                    }
+                }
+                write!(f, "}}")
+            }
+            // the settle predicates pad their arrays with zeroes, only the used slots matter
+            Decoded::Array(items) => {
+                write!(f, "[")?;
+                let mut first = true;
+                for (i, item) in items.iter().enumerate().filter(|(_, item)| !item.is_zero()) {
+                    write!(f, "{}{}: {}", if first { "" } else { ", " }, i, item)?;
+                    first = false;
+                }
+                let empty = items.iter().filter(|item| item.is_zero()).count();
+                if empty > 0 {
+                    write!(f, "{}{} empty", if first { "" } else { ", " }, empty)?;
+                }
+                write!(f, "]")
+            }
+            Decoded::Raw(words) => write!(f, "raw {:?}", words),
+        }
+    }
+}
+
+/// Name of the predicate of the standard contract, the one `crate::abi` was generated from,
+/// `address` points to, or `"unknown"`.
+pub fn predicate_name(address: &PredicateAddress) -> &'static str {
+    let predicates = [
+        (deposit::ADDRESS, "deposit"),
+        (withdraw::ADDRESS, "withdraw"),
+        (addLimitOrderBid::ADDRESS, "addLimitOrderBid"),
+        (addLimitOrderBidBehind::ADDRESS, "addLimitOrderBidBehind"),
+        (addLimitOrderAsk::ADDRESS, "addLimitOrderAsk"),
+        (addLimitOrderAskBehind::ADDRESS, "addLimitOrderAskBehind"),
+        (removeLimitOrderBid::ADDRESS, "removeLimitOrderBid"),
+        (removeLimitOrderAsk::ADDRESS, "removeLimitOrderAsk"),
+        (addLimitOrdersBid::ADDRESS, "addLimitOrdersBid"),
+        (addLimitOrdersAsk::ADDRESS, "addLimitOrdersAsk"),
+        (settle::ADDRESS, "settle"),
+        (settleMarketOrders::ADDRESS, "settleMarketOrders"),
+    ];
+    predicates
+        .into_iter()
+        .find(|(predicate, _)| predicate == address)
+        .map_or("unknown", |(_, name)| name)
+}
+
+/// Names `solution`'s predicate in `contract` and decodes its `predicate_data` var by var
+/// with the contract's ABI. Solutions built for the standard contract are named after it,
+/// and vars are left raw when the ABI is not known.
+pub fn describe(solution: &Solution, contract: &ContractVariant) -> SolutionReport {
+    let address = &solution.predicate_to_solve;
+    let predicate = contract.predicate_name(address).unwrap_or_else(|| predicate_name(address)).to_string();
+    let abi = contract.abi().and_then(|abi| {
+        abi.predicates.iter().find(|p| p.name.trim_start_matches("::") == predicate)
+    });
+    let vars = solution
+        .predicate_data
+        .iter()
+        .enumerate()
+        .map(|(i, value)| match abi.and_then(|abi| abi.params.get(i)) {
+            Some(param) => (param.name.trim_start_matches("::").to_string(), decode_value(&param.ty, value)),
+            None => (format!("var {}", i), Decoded::Raw(value.clone())),
+        })
+        .collect();
+    SolutionReport { predicate, vars, mutations: solution.state_mutations.len() }
+}
+
+/// Readable text for a builder `failed` list, given the solution sets that were submitted
+/// for `contract`. Handy in tests:
+/// `assert!(o.failed.is_empty(), "{}", explain(&o.failed, &[&solution_set], &contract))`.
+pub fn explain(failed: &[(ContentAddress, u32, InvalidSet)], solution_sets: &[&SolutionSet], contract: &ContractVariant) -> String {
+    let mut log = SubmissionLog::new(contract.clone());
+    for (i, solution_set) in solution_sets.iter().enumerate() {
+        log.record(format!("solution set {}", i), solution_set);
+    }
+    log.explain(failed).iter().map(|set| set.to_string()).collect()
+}
+
+fn decode_value(ty: &TypeABI, value: &Value) -> Decoded {
+    let mut words = value.as_slice();
+    match decode(ty, &mut words) {
+        Some(decoded) if words.is_empty() => decoded,
+        _ => Decoded::Raw(value.clone()),
+    }
+}
+
+fn decode(ty: &TypeABI, words: &mut &[Word]) -> Option<Decoded> {
+    match ty {
+        TypeABI::Int => take(words, 1).map(|w| Decoded::Int(w[0])),
+        TypeABI::Bool => take(words, 1).map(|w| Decoded::Bool(w[0] != 0)),
+        TypeABI::B256 => take(words, 4).map(|w| Decoded::B256([w[0], w[1], w[2], w[3]])),
+        TypeABI::Tuple(fields) => fields
+            .iter()
+            .map(|field| Some((field.name.clone(), decode(&field.ty, words)?)))
+            .collect::<Option<Vec<_>>>()
+            .map(Decoded::Tuple),
+        TypeABI::Array { ty, size } => (0..*size)
+            .map(|_| decode(ty, words))
+            .collect::<Option<Vec<_>>>()
+            .map(Decoded::Array),
+        // the orderbook's predicates only take ints, b256s and tuples and arrays of them
+        _ => None,
+    }
+}
+
+fn take<'a>(words: &mut &'a [Word], n: usize) -> Option<&'a [Word]> {
+    if words.len() < n {
+        return None;
+    }
+    let (head, tail) = words.split_at(n);
+    *words = tail;
+    Some(head)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solutions::produce_solution_deposit;
+    use essential_types::{Program, contract::Contract};
+
+    const OUT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../PintLOB/orderbook/out/debug");
+
+    fn abi_json() -> String {
+        std::fs::read_to_string(format!("{}/orderbook-abi.json", OUT)).unwrap()
+    }
+
+    fn standard() -> ContractVariant {
+        ContractVariant::standard().with_abi(serde_json::from_str(&abi_json()).unwrap())
+    }
+
+    #[test]
+    fn describes_deposit() {
+        let solution = produce_solution_deposit(5, 105, 7, 107, [1; 4], [1; 4], [1; 4]);
+        let report = describe(&solution, &standard());
+        assert_eq!(report.predicate, "deposit");
+        assert_eq!(report.vars[0], ("amount0".to_string(), Decoded::Int(5)));
+        assert_eq!(report.vars[1], ("amount1".to_string(), Decoded::Int(7)));
+        assert_eq!(report.mutations, 2);
+
+        // without an ABI the vars stay raw
+        let report = describe(&solution, &ContractVariant::standard());
+        assert_eq!(report.vars[0], ("var 0".to_string(), Decoded::Raw(vec![5])));
+    }
+
+    #[test]
+    fn names_predicates_of_the_deployed_contract() {
+        let compiled = std::fs::read_to_string(format!("{}/orderbook.json", OUT)).unwrap();
+        let (mut contract, _): (Contract, Vec<Program>) = serde_json::from_str(&compiled).unwrap();
+        contract.salt = [1; 32];
+        let variant = ContractVariant::from_compiled(&contract, &abi_json(), 10).unwrap();
+        let solution = variant.retarget(produce_solution_deposit(5, 105, 7, 107, [1; 4], [1; 4], [1; 4])).unwrap();
+
+        assert_eq!(describe(&solution, &standard()).predicate, "unknown");
+        let report = describe(&solution, &variant);
+        assert_eq!(report.predicate, "deposit");
+        assert_eq!(report.vars[1], ("amount1".to_string(), Decoded::Int(7)));
+    }
+
+    #[test]
+    fn explains_recorded_sets_only() {
+        let solution_set = SolutionSet { solutions: vec![produce_solution_deposit(5, 5, 0, 0, [1; 4], [1; 4], [1; 4])] };
+        let mut log = SubmissionLog::new(standard());
+        let address = log.record("deposit for 0x01", &solution_set);
+        let unknown = essential_hash::content_addr(&SolutionSet { solutions: vec![] });
+
+        let reports = log.explain(&[(address, 0, InvalidSet::PredicateInvalid), (unknown, 3, InvalidSet::PredicateInvalid)]);
+        assert_eq!(reports[0].intent.as_deref(), Some("deposit for 0x01"));
+        assert_eq!(reports[0].solutions[0].predicate, "deposit");
+        assert_eq!(reports[1].intent, None);
+        assert!(reports[1].solutions.is_empty());
+        assert!(reports[1].to_string().contains("#3"), "{}", reports[1]);
+    }
+
+    #[test]
+    fn arrays_hide_padding() {
+        let padded = Decoded::Array(vec![Decoded::Int(3), Decoded::Int(0), Decoded::Int(0)]);
+        assert_eq!(padded.to_string(), "[0: 3, 2 empty]");
+    }
+}
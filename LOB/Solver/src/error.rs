@@ -0,0 +1,54 @@
+//! Error type shared by the whole solver.
+use std::fmt;
+
+/// Everything that can go wrong while building, submitting or checking solutions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SolverError {
+    /// Malformed input: bad hex, wrong word count, unparsable numbers.
+    Decode(String),
+    /// A storage key could not be derived from the ABI.
+    Key(String),
+    /// The builder did not accept the solution set.
+    Submit(String),
+    /// The node could not validate the solution set.
+    Validation(String),
+    /// The builder failed to produce a block.
+    Build(String),
+    /// A block was built but some solution sets in it failed.
+    BlockFailed(String),
+    /// Reading contract state from the node failed.
+    Query(String),
+    /// A balance or notional does not fit in the contract's 64 bit integers.
+    Overflow(String),
+    /// Compiling or registering the contract failed.
+    Deploy(String),
+    /// Reading a file failed.
+    Io(String),
+}
+
+pub type Result<T> = std::result::Result<T, SolverError>;
+
+impl fmt::Display for SolverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolverError::Decode(e) => write!(f, "decode error: {}", e),
+            SolverError::Key(e) => write!(f, "key derivation error: {}", e),
+            SolverError::Submit(e) => write!(f, "submit error: {}", e),
+            SolverError::Validation(e) => write!(f, "validation error: {}", e),
+            SolverError::Build(e) => write!(f, "block build error: {}", e),
+            SolverError::BlockFailed(e) => write!(f, "block failed: {}", e),
+            SolverError::Query(e) => write!(f, "state query error: {}", e),
+            SolverError::Overflow(e) => write!(f, "arithmetic overflow: {}", e),
+            SolverError::Deploy(e) => write!(f, "deploy error: {}", e),
+            SolverError::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SolverError {}
+
+impl From<std::io::Error> for SolverError {
+    fn from(e: std::io::Error) -> Self {
+        SolverError::Io(e.to_string())
+    }
+}
@@ -0,0 +1,131 @@
+//! Fixed-point encoding of prices and quantities.
+//!
+//! The contract only knows integers: `max_amnt` is counted in lots of token1 and `price`
+//! in token0 base units per lot, so `max_amnt * price` is exactly the token0 that changes
+//! hands and `balances_1` moves in lots. [`Decimals`] converts between that encoding and
+//! human readable decimal numbers.
+use crate::error::{Result, SolverError};
+
+/// How prices and quantities of one market are scaled on chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decimals {
+    /// Decimals of token1, the traded asset.
+    pub base_decimals: u32,
+    /// Decimals of token0, the quote asset.
+    pub quote_decimals: u32,
+    /// Size of one lot in token1 base units. On-chain quantities count lots.
+    pub lot: i64,
+    /// Smallest price step in on-chain price units. Prices are rounded to it.
+    pub tick: i64,
+}
+
+impl Default for Decimals {
+    fn default() -> Self {
+        Self::legacy()
+    }
+}
+
+impl Decimals {
+    /// The scaling the solver used before it was configurable: both prices and quantities
+    /// are multiplied by 10. One lot is 0.1 token1 and token0 has two decimals, so a price
+    /// of 100.5 becomes 100.5 * 100 * 0.1 = 1005 per lot.
+    pub const fn legacy() -> Self {
+        Self { base_decimals: 1, quote_decimals: 2, lot: 1, tick: 1 }
+    }
+
+    /// Integer price per lot, rounded to the nearest tick.
+    pub fn price_to_chain(&self, price: f64) -> Result<i64> {
+        let per_lot = price * 10f64.powi(self.quote_decimals as i32) * self.lot as f64
+            / 10f64.powi(self.base_decimals as i32);
+        let ticks = to_int(per_lot / self.tick as f64, "price", price)?;
+        ticks
+            .checked_mul(self.tick)
+            .ok_or_else(|| SolverError::Decode(format!("price {} overflows", price)))
+    }
+
+    /// Number of whole lots in `quantity`, rounded to the nearest lot.
+    pub fn quantity_to_chain(&self, quantity: f64) -> Result<i64> {
+        to_int(quantity * 10f64.powi(self.base_decimals as i32) / self.lot as f64, "quantity", quantity)
+    }
+
+    pub fn price_from_chain(&self, price: i64) -> f64 {
+        price as f64 * 10f64.powi(self.base_decimals as i32)
+            / (10f64.powi(self.quote_decimals as i32) * self.lot as f64)
+    }
+
+    /// Token1 quantity of `lots`, failing if it does not fit in token1 base units.
+    pub fn quantity_from_chain(&self, lots: i64) -> Result<f64> {
+        let units = lots
+            .checked_mul(self.lot)
+            .ok_or_else(|| SolverError::Decode(format!("quantity of {} lots cannot be decoded", lots)))?;
+        Ok(units as f64 / 10f64.powi(self.base_decimals as i32))
+    }
+
+    /// Token0 amount, as a decimal number, of `lots` at on-chain `price`.
+    pub fn notional_from_chain(&self, lots: i64, price: i64) -> f64 {
+        lots as f64 * price as f64 / 10f64.powi(self.quote_decimals as i32)
+    }
+}
+
+/// Token0 base units exchanged when `lots` trade at `price`. This is the product the
+/// contract checks balances against, so it has to fit in an `i64`.
+pub fn notional(lots: i64, price: i64) -> Result<i64> {
+    lots.checked_mul(price)
+        .ok_or_else(|| SolverError::Overflow(format!("{} lots at {}", lots, price)))
+}
+
+/// `a + b`, failing instead of wrapping.
+pub fn add(a: i64, b: i64) -> Result<i64> {
+    a.checked_add(b)
+        .ok_or_else(|| SolverError::Overflow(format!("{} + {}", a, b)))
+}
+
+/// `a - b`, failing instead of wrapping.
+pub fn sub(a: i64, b: i64) -> Result<i64> {
+    a.checked_sub(b)
+        .ok_or_else(|| SolverError::Overflow(format!("{} - {}", a, b)))
+}
+
+fn to_int(value: f64, what: &str, input: f64) -> Result<i64> {
+    let rounded = value.round();
+    if !rounded.is_finite() || rounded < 0.0 || rounded > i64::MAX as f64 {
+        return Err(SolverError::Decode(format!("{} {} cannot be encoded", what, input)));
+    }
+    Ok(rounded as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_scales_by_ten() {
+        let decimals = Decimals::legacy();
+        assert_eq!(decimals.price_to_chain(100.5).unwrap(), 1005);
+        assert_eq!(decimals.quantity_to_chain(1.5).unwrap(), 15);
+        assert_eq!(decimals.price_from_chain(1005), 100.5);
+        assert_eq!(decimals.quantity_from_chain(15).unwrap(), 1.5);
+        // 1.5 token1 at 100.5 is 150.75 token0
+        assert_eq!(decimals.notional_from_chain(15, 1005), 150.75);
+    }
+
+    #[test]
+    fn rounds_to_tick_and_lot() {
+        // token1 with 8 decimals traded in lots of 0.001, token0 with 6 decimals
+        let decimals = Decimals { base_decimals: 8, quote_decimals: 6, lot: 100_000, tick: 50 };
+        // 25000.013 per token1 is 25_000_013 per lot, rounded to a multiple of 50
+        assert_eq!(decimals.price_to_chain(25_000.013).unwrap(), 25_000_000);
+        assert_eq!(decimals.quantity_to_chain(0.0123).unwrap(), 12);
+        assert!(decimals.quantity_to_chain(-1.0).is_err());
+        assert!(decimals.price_to_chain(f64::NAN).is_err());
+        assert!(matches!(decimals.quantity_from_chain(i64::MAX), Err(SolverError::Decode(_))));
+    }
+
+    #[test]
+    fn checked_maths_reports_overflow() {
+        assert_eq!(notional(15, 1005).unwrap(), 15_075);
+        assert!(matches!(notional(i64::MAX / 2, 3), Err(SolverError::Overflow(_))));
+        assert!(add(i64::MAX, 1).is_err());
+        assert!(sub(i64::MIN, 1).is_err());
+    }
+}
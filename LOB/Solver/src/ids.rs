@@ -0,0 +1,218 @@
+//! Indices for new `bid_orders`/`ask_orders` slots.
+//!
+//! An add writes whatever slot it is given, so an index that belongs to a resting order
+//! on either side would overwrite it. [`OrderIdAllocator`] only hands out indices that are
+//! free in the mirror and not handed out before, and [`OrderIdAllocator::next_free`] also
+//! checks contract state. An add only takes a nil slot. `settle` clears the slots of the
+//! orders it fills in full, so [`OrderIdAllocator::reclaim`] hands those indices out again,
+//! before new ones, once the chain shows them nil. Slots zeroed by a remove stay taken.
+use crate::book::OrderBook;
+use crate::error::{Result, SolverError};
+use crate::state::StateReader;
+use essential_types::Word;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Deserialize;
+use sha3::{Digest, Keccak256};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+// Candidates drawn before giving up on finding a free index.
+const MAX_ATTEMPTS: usize = 1 << 16;
+
+/// How an [`OrderIdAllocator`] picks new indices.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdScheme {
+    /// Pseudo random indices in `1..=u32::MAX`, the same sequence for the same seed.
+    Deterministic { seed: u64 },
+    /// 1, 2, 3, ...
+    #[default]
+    Monotonic,
+    /// keccak256 of the order's address and a per-address nonce, folded into
+    /// `1..=u32::MAX`.
+    Hashed,
+}
+
+/// Hands out unique order indices.
+#[derive(Debug, Clone)]
+pub struct OrderIdAllocator {
+    scheme: IdScheme,
+    rng: StdRng,
+    next: i64,
+    nonces: HashMap<[Word; 4], u64>,
+    /// Released indices, reused smallest first.
+    recycled: BTreeSet<i64>,
+    /// Indices handed out and not released since.
+    issued: HashSet<i64>,
+}
+
+impl Default for OrderIdAllocator {
+    fn default() -> Self {
+        Self::new(IdScheme::default())
+    }
+}
+
+impl OrderIdAllocator {
+    pub fn new(scheme: IdScheme) -> Self {
+        let seed = match scheme {
+            IdScheme::Deterministic { seed } => seed,
+            _ => 0,
+        };
+        Self {
+            scheme,
+            rng: StdRng::seed_from_u64(seed),
+            next: 1,
+            nonces: HashMap::new(),
+            recycled: BTreeSet::new(),
+            issued: HashSet::new(),
+        }
+    }
+
+    pub fn scheme(&self) -> IdScheme {
+        self.scheme
+    }
+
+    /// An index for a new order of `addr` that no order in `orderbook` has and that was not
+    /// handed out before. Indices handed out stay taken until they are released, so several
+    /// orders can be planned before any of them reaches the mirror.
+    pub fn next(&mut self, orderbook: &OrderBook, addr: [Word; 4]) -> Result<i64> {
+        let taken = |index: i64| orderbook.get(index, true).is_some() || orderbook.get(index, false).is_some();
+        while let Some(index) = self.recycled.pop_first() {
+            if !taken(index) && self.issued.insert(index) {
+                return Ok(index);
+            }
+        }
+        for _ in 0..MAX_ATTEMPTS {
+            let index = self.candidate(addr)?;
+            if !taken(index) && self.issued.insert(index) {
+                return Ok(index);
+            }
+        }
+        Err(SolverError::Validation(format!("no free order index after {} attempts", MAX_ATTEMPTS)))
+    }
+
+    /// [`Self::next`], also skipping indices whose slot is not vacant on chain, e.g. one
+    /// holding an order of another solver the mirror doesn't track. Skipped indices are
+    /// released again, so the chain is checked anew the next time they come up.
+    pub async fn next_free(&mut self, orderbook: &OrderBook, state: &StateReader<'_>, addr: [Word; 4]) -> Result<i64> {
+        let mut skipped = vec![];
+        let found = self.find_free(orderbook, state, addr, &mut skipped).await;
+        for index in skipped {
+            self.release(index);
+        }
+        found
+    }
+
+    async fn find_free(&mut self, orderbook: &OrderBook, state: &StateReader<'_>, addr: [Word; 4], skipped: &mut Vec<i64>) -> Result<i64> {
+        for _ in 0..MAX_ATTEMPTS {
+            let index = self.next(orderbook, addr)?;
+            skipped.push(index);
+            if vacant(state, index).await? {
+                skipped.pop();
+                return Ok(index);
+            }
+        }
+        Err(SolverError::Validation(format!("no free order index on chain after {} attempts", MAX_ATTEMPTS)))
+    }
+
+    /// Gives back the index of an order that was settled in full or removed, once its block
+    /// has been built, or of an order whose add was never built.
+    pub fn release(&mut self, index: i64) {
+        if index > 0 && self.issued.remove(&index) {
+            self.recycled.insert(index);
+        }
+    }
+
+    /// Releases every index of `indices` that no longer rests in `orderbook` and whose
+    /// slots are vacant on chain, e.g. the orders a settlement filled in full once it has
+    /// been built and applied to the mirror.
+    pub async fn reclaim(&mut self, orderbook: &OrderBook, state: &StateReader<'_>, indices: impl IntoIterator<Item = i64>) -> Result<()> {
+        for index in indices {
+            let resting = orderbook.get(index, true).is_some() || orderbook.get(index, false).is_some();
+            if !resting && vacant(state, index).await? {
+                self.release(index);
+            }
+        }
+        Ok(())
+    }
+
+    fn candidate(&mut self, addr: [Word; 4]) -> Result<i64> {
+        match self.scheme {
+            IdScheme::Deterministic { .. } => Ok(self.rng.gen_range(1..=u32::MAX) as i64),
+            IdScheme::Monotonic => {
+                let index = self.next;
+                self.next = index
+                    .checked_add(1)
+                    .ok_or_else(|| SolverError::Overflow("monotonic order indices ran out".to_string()))?;
+                Ok(index)
+            }
+            IdScheme::Hashed => {
+                let nonce = self.nonces.entry(addr).or_default();
+                let index = hashed_index(addr, *nonce);
+                *nonce += 1;
+                Ok(index)
+            }
+        }
+    }
+}
+
+// Whether an add could take `index` on either side.
+async fn vacant(state: &StateReader<'_>, index: i64) -> Result<bool> {
+    Ok(state.is_vacant(index, true).await? && state.is_vacant(index, false).await?)
+}
+
+/// keccak256 of `addr` and `nonce`, big endian, folded into `1..=u32::MAX`.
+pub fn hashed_index(addr: [Word; 4], nonce: u64) -> i64 {
+    let mut hasher = Keccak256::new();
+    for word in addr {
+        hasher.update(word.to_be_bytes());
+    }
+    hasher.update(nonce.to_be_bytes());
+    let hash = hasher.finalize();
+    let head = u64::from_be_bytes(hash[..8].try_into().unwrap_or_default());
+    (head % u32::MAX as u64) as i64 + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::Order;
+
+    fn order(index: i64) -> Order {
+        Order { index, max_amnt: 10, price: 100, is_bid: index % 2 == 0, addr: [1; 4], auth: [1; 4] }
+    }
+
+    #[test]
+    fn skips_taken_indices_and_recycles_released_ones() {
+        let mut orderbook = OrderBook::new();
+        orderbook.insert(order(2));
+        orderbook.insert(order(3));
+        let mut ids = OrderIdAllocator::new(IdScheme::Monotonic);
+        let first: Vec<_> = (0..3).map(|_| ids.next(&orderbook, [1; 4]).unwrap()).collect();
+        assert_eq!(first, vec![1, 4, 5]);
+
+        // 4 was settled, 3 and 9 never came from this allocator
+        ids.release(3);
+        ids.release(4);
+        ids.release(9);
+        assert_eq!(ids.next(&orderbook, [1; 4]).unwrap(), 4);
+        assert_eq!(ids.next(&orderbook, [1; 4]).unwrap(), 6);
+    }
+
+    #[test]
+    fn seeded_and_hashed_schemes_are_reproducible() {
+        let orderbook = OrderBook::new();
+        let draw = |scheme| {
+            let mut ids = OrderIdAllocator::new(scheme);
+            (0..5).map(|_| ids.next(&orderbook, [7; 4]).unwrap()).collect::<Vec<_>>()
+        };
+        for scheme in [IdScheme::Deterministic { seed: 42 }, IdScheme::Hashed] {
+            let indices = draw(scheme);
+            assert_eq!(indices, draw(scheme));
+            assert!(indices.iter().all(|&i| (1..=u32::MAX as i64).contains(&i)));
+            assert_eq!(indices.iter().collect::<HashSet<_>>().len(), 5);
+        }
+        assert_eq!(draw(IdScheme::Hashed)[0], hashed_index([7; 4], 0));
+        assert_ne!(hashed_index([7; 4], 0), hashed_index([8; 4], 0));
+    }
+}
@@ -0,0 +1,141 @@
+//! Address decoding, storage key derivation and index generation.
+use crate::abi::storage;
+use crate::error::{Result, SolverError};
+use essential_types::{convert::{word_4_from_u8_32, words_from_hex_str}, Key, Word};
+use hex::decode;
+use rand::Rng;
+use rand::rngs::StdRng;
+use sha3::{Digest, Keccak256};
+use std::convert::TryInto;
+
+// Function to convert a hex string to [i64;4]
+/// Decodes a (possibly `0x` prefixed) 32 byte hex string into a `b256` word array.
+/// Shorter inputs are zero padded on the right.
+pub fn hex_to_i64_array(hex_str: &str) -> Result<[i64; 4]> {
+    // Remove "0x" prefix if present
+    let clean_hex = hex_str.trim_start_matches("0x");
+    // println!("clean_hex: {:?}", clean_hex);
+    
+    // Convert hex string to bytes
+    let mut bytes = [0u8; 32]; // 256 bits = 32 bytes
+    let decoded = decode(clean_hex)
+        .map_err(|e| SolverError::Decode(format!("invalid hex string {:?}: {}", hex_str, e)))?;
+    // println!("decoded: {:?}", decoded);
+    if decoded.len() > 32 {
+        return Err(SolverError::Decode(format!("{:?} is longer than 32 bytes", hex_str)));
+    }
+    
+    // Copy decoded bytes to our fixed-size array, handling the case where input might be too short
+    bytes[..decoded.len()].copy_from_slice(&decoded);
+    
+    // Use the imported word_4_from_u8_32 function to convert bytes to [Word; 4]
+    let word_array: [Word; 4] = word_4_from_u8_32(bytes);
+    // println!("word_array: {:?}", word_array);
+    Ok(word_array)
+
+}
+
+/// Decodes a (possibly `0x` prefixed) hex string that must be exactly 4 words long.
+pub fn hex_to_word_array(hex_str: &str) -> Result<[Word; 4]> {
+    let clean_hex = hex_str.trim_start_matches("0x");
+    let word_vec = words_from_hex_str(clean_hex)
+        .map_err(|e| SolverError::Decode(format!("invalid hex string {:?}: {:?}", hex_str, e)))?;
+    let len = word_vec.len();
+    word_vec
+        .try_into()
+        .map_err(|_| SolverError::Decode(format!("expected 4 words, got {}", len)))
+}
+
+/// Storage key of `balances_0[address]`.
+pub fn balances_0_key(address: [Word; 4]) -> Result<Key> {
+    let balance: Vec<_> = storage::keys()
+        .balances_0(|e| e.entry(address))
+        .into();
+    balance
+        .into_iter()
+        .next()
+        .ok_or_else(|| SolverError::Key("no key for balances_0".to_string()))
+}
+
+/// Storage key of `balances_1[address]`.
+pub fn balances_1_key(address: [Word; 4]) -> Result<Key> {
+    let balance: Vec<_> = storage::keys()
+        .balances_1(|e| e.entry(address))
+        .into();
+    balance
+        .into_iter()
+        .next()
+        .ok_or_else(|| SolverError::Key("no key for balances_1".to_string()))
+}
+
+/// Storage key of the first field (`max_amnt`) of `bid_orders[index]`.
+pub fn fetch_bid_order_keys(index: i64) -> Result<Key> {
+    let keys: Vec<Key> = storage::keys()
+    .bid_orders(|map| map.entry(index, |tup| 
+    tup.max_amnt()
+    .price()
+    .isBid()
+    .addr()
+    .auth()
+    .next_key()
+    )
+    )
+    .into();
+    keys.into_iter()
+        .next()
+        .ok_or_else(|| SolverError::Key(format!("no key for bid_orders[{}]", index)))
+}
+
+/// Storage keys of every field of `bid_orders[index]` or `ask_orders[index]`, in the order
+/// `max_amnt, price, isBid, addr, auth, next_key`.
+pub fn order_keys(index: i64, is_bid: bool) -> Result<[Key; 6]> {
+    let keys: Vec<Key> = if is_bid {
+        storage::keys()
+            .bid_orders(|map| map.entry(index, |tup| tup.max_amnt().price().isBid().addr().auth().next_key()))
+            .into()
+    } else {
+        storage::keys()
+            .ask_orders(|map| map.entry(index, |tup| tup.max_amnt().price().isBid().addr().auth().next_key()))
+            .into()
+    };
+    let len = keys.len();
+    keys.try_into()
+        .map_err(|_| SolverError::Key(format!("expected 6 keys for order {}, got {}", index, len)))
+}
+
+/// Storage key of `first_bid_order` or `first_ask_order`.
+pub fn first_order_key(is_bid: bool) -> Result<Key> {
+    let keys: Vec<Key> = if is_bid {
+        storage::keys().first_bid_order().into()
+    } else {
+        storage::keys().first_ask_order().into()
+    };
+    keys.into_iter()
+        .next()
+        .ok_or_else(|| SolverError::Key("no key for the first order".to_string()))
+}
+
+/// Storage key of `number_of_bid_orders` or `number_of_ask_orders`.
+pub fn order_count_key(is_bid: bool) -> Result<Key> {
+    let keys: Vec<Key> = if is_bid {
+        storage::keys().number_of_bid_orders().into()
+    } else {
+        storage::keys().number_of_ask_orders().into()
+    };
+    keys.into_iter()
+        .next()
+        .ok_or_else(|| SolverError::Key("no key for the order count".to_string()))
+}
+
+/// Random keccak256 hash, hex encoded with a `0x` prefix. Useful to generate addresses.
+pub fn generate_random_hash(rng: &mut StdRng) -> String {
+    let mut random_bytes = [0u8; 32];
+    rng.fill(&mut random_bytes);
+    let hash = Keccak256::digest(random_bytes);
+    format!("0x{}", hex::encode(hash))
+}
+/// Random non-zero order index (0 is the nil order). Nothing stops it from colliding
+/// with a resting order; [`crate::ids::OrderIdAllocator`] hands out free ones.
+pub fn generate_index(rng: &mut StdRng) -> i64 {
+    rng.gen_range(1..=u32::MAX) as i64   // excludes 0
+}
@@ -0,0 +1,59 @@
+//! Solver for the Pint price-time-priority orderbook contract.
+//!
+//! - [`solutions`] builds a `Solution` for every predicate of the contract.
+//! - [`book`] is the off-chain mirror of the on-chain linked-list orderbook.
+//! - [`matching`] crosses bids against asks in price-time priority and reports the fills.
+//! - [`settlement`] splits any number of fills into `settle` solutions and plans
+//!   `settleMarketOrders`.
+//! - [`strategy`] prices the solver's own orders in a `settle` solution.
+//! - [`market`] queues market orders and allocates them pro rata for `settleMarketOrders`.
+//! - [`preflight`] checks settle solutions against the predicates' constraints before they
+//!   are submitted.
+//! - [`state`] reads contract storage into the crate's types.
+//! - [`loader`] rebuilds the mirror from contract state after a restart.
+//! - [`reconcile`] diffs the mirror against contract state.
+//! - [`fixed`] converts decimal prices and quantities to the contract's integers.
+//! - [`keys`] decodes addresses and derives storage keys.
+//! - [`ids`] hands out order indices that don't collide with resting orders.
+//! - [`trace`] parses recorded orderbook traces and [`replay`] drives them through the contract.
+//! - [`diagnostics`] explains failed solution sets by predicate, intent and decoded data.
+//! - [`variant`] renders and addresses contracts with another settlement batch size.
+//! - [`batch`] packs solutions that write disjoint keys into shared solution sets, and
+//!   [`conflicts`] reports read/write overlaps between solutions and suggests blocks.
+//! - [`node`] wraps the builder and node calls, returning [`SolverError`] instead of panicking.
+//!
+//! The contract ABI generated from `../PintLOB/orderbook/out/debug` lives in [`abi`], so the
+//! contract must be built with `pint build` before this crate compiles.
+pub mod abi;
+pub mod batch;
+pub mod book;
+pub mod conflicts;
+pub mod diagnostics;
+pub mod error;
+pub mod fixed;
+pub mod ids;
+pub mod keys;
+pub mod loader;
+pub mod market;
+pub mod matching;
+pub mod node;
+pub mod preflight;
+pub mod reconcile;
+pub mod replay;
+pub mod settlement;
+pub mod solutions;
+pub mod state;
+pub mod strategy;
+pub mod trace;
+pub mod variant;
+
+pub use error::{Result, SolverError};
+pub use fixed::Decimals;
+pub use book::{Balances, CancelPlan, InsertPlan, Order, OrderBook, PriceLevel};
+pub use solutions::{LimitOrder, MarketOrder, SettleOrder};
+pub use state::StateReader;
+
+#[cfg(test)]
+mod test_chain;
+#[cfg(test)]
+mod tests;
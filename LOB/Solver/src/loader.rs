@@ -0,0 +1,48 @@
+//! Rebuilds the `OrderBook` mirror from contract state.
+//!
+//! Each side is read by starting at `first_bid_order`/`first_ask_order` and following
+//! `next_key` until it reaches the nil order 0. Because the contract keeps both lists in
+//! price-time order, appending every order to its level as it is read reproduces the
+//! mirror exactly.
+use crate::book::{Order, OrderBook};
+use crate::error::{Result, SolverError};
+use crate::state::StateReader;
+use std::collections::HashSet;
+
+/// Reads both linked lists into a fresh `OrderBook`.
+pub async fn load_orderbook(state: &StateReader<'_>) -> Result<OrderBook> {
+    let mut orderbook = OrderBook::new();
+    for is_bid in [true, false] {
+        for order in load_side(state, is_bid).await? {
+            orderbook.insert(order);
+        }
+    }
+    Ok(orderbook)
+}
+
+/// Reads one linked list, in on-chain order.
+pub async fn load_side(state: &StateReader<'_>, is_bid: bool) -> Result<Vec<Order>> {
+    let mut orders = vec![];
+    let mut seen = HashSet::new();
+    let mut index = if is_bid { state.first_bid().await? } else { state.first_ask().await? };
+    while index != 0 {
+        if !seen.insert(index) {
+            return Err(SolverError::Decode(format!("linked list loops back to order {}", index)));
+        }
+        let (order, next_key) = load_order(state, index, is_bid)
+            .await?
+            .ok_or_else(|| SolverError::Decode(format!("linked list points to empty order {}", index)))?;
+        orders.push(order);
+        index = next_key;
+    }
+    Ok(orders)
+}
+
+/// Reads `bid_orders[index]` or `ask_orders[index]` as a mirror order together with its
+/// `next_key`. Returns `None` for an empty slot.
+pub async fn load_order(state: &StateReader<'_>, index: i64, is_bid: bool) -> Result<Option<(Order, i64)>> {
+    Ok(state.order(index, is_bid).await?.map(|o| {
+        let order = Order { index, max_amnt: o.max_amnt, price: o.price, is_bid, addr: o.addr, auth: o.auth };
+        (order, o.next_key)
+    }))
+}
@@ -0,0 +1,293 @@
+//! Intake and pro-rata allocation of market orders for `settleMarketOrders`.
+//!
+//! Market orders wait in a [`MarketQueue`] until both sides have some. Market bids are
+//! filled by the resting asks at the front of the book and market asks by the resting bids,
+//! each at the average price of the limit orders they consume. The predicate sees one
+//! batch of limit orders per side, [`SETTLE_BATCH`] for the standard contract, so when the
+//! queue asks for more than those hold, every queued order gets its pro-rata share and
+//! keeps the rest queued.
+//!
+//! The predicate constrains each address's balances independently, so no address may
+//! appear twice in one solution. Limit orders are taken first, as a prefix of their
+//! linked list that stops at the first repeated owner. Market orders from an address that
+//! is already in the solution stay queued for a later round.
+use crate::book::{Balances, Order, OrderBook};
+use crate::error::{Result, SolverError};
+use crate::fixed::add;
+use crate::settlement::{Fill, MarketChunk, SETTLE_BATCH, plan_market_settle};
+use crate::solutions::MarketOrder;
+use essential_types::{Word, solution::Solution};
+use std::collections::{HashSet, VecDeque};
+
+/// Market orders waiting to be settled, oldest first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarketQueue {
+    pub bids: VecDeque<MarketOrder>,
+    pub asks: VecDeque<MarketOrder>,
+    /// Most limit and market orders per side in one solution.
+    pub batch: usize,
+}
+
+impl Default for MarketQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MarketQueue {
+    pub fn new() -> Self {
+        Self::with_batch(SETTLE_BATCH)
+    }
+
+    /// A queue for a contract variant taking `batch` orders per side.
+    pub fn with_batch(batch: usize) -> Self {
+        Self { bids: VecDeque::new(), asks: VecDeque::new(), batch }
+    }
+
+    pub fn push(&mut self, order: MarketOrder, is_bid: bool) {
+        if is_bid {
+            self.bids.push_back(order);
+        } else {
+            self.asks.push_back(order);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bids.len() + self.asks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bids.is_empty() && self.asks.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.bids.clear();
+        self.asks.clear();
+    }
+
+    /// Plans one `settleMarketOrders` solution for the front of the queue.
+    ///
+    /// Returns `None` when a side has nothing to trade: the predicate divides by both sides'
+    /// limit volume, so it cannot settle one side alone.
+    pub fn plan(&self, orderbook: &OrderBook, balances: &Balances, solver_addr: [Word; 4]) -> Result<Option<MarketPlan>> {
+        if self.bids.is_empty() || self.asks.is_empty() {
+            return Ok(None);
+        }
+        // the solver's balances take up the price rounding, so it cannot trade as well
+        let mut taken = HashSet::from([solver_addr]);
+        let resting_bids = resting_slice(orderbook, true, self.batch, &mut taken);
+        let resting_asks = resting_slice(orderbook, false, self.batch, &mut taken);
+        let bid_batch = batch(&self.bids, self.batch, &mut taken);
+        let ask_batch = batch(&self.asks, self.batch, &mut taken);
+
+        // market bids consume resting asks and market asks consume resting bids
+        let (Some((bids, ask_fills)), Some((asks, bid_fills))) =
+            (allocate(&bid_batch, &resting_asks)?, allocate(&ask_batch, &resting_bids)?)
+        else {
+            return Ok(None);
+        };
+
+        let bid_market: Vec<MarketOrder> = bids.iter().map(|a| a.order).collect();
+        let ask_market: Vec<MarketOrder> = asks.iter().map(|a| a.order).collect();
+        let chunk = plan_market_settle(orderbook, balances, &bid_market, &ask_market, &bid_fills, &ask_fills, solver_addr, self.batch)?;
+        Ok(Some(MarketPlan { chunk, bids, asks }))
+    }
+}
+
+/// How much of one queued market order goes into the solution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Allocation {
+    /// Position of the order in its side of the queue when it was planned.
+    pub position: usize,
+    /// The queued order with `amount` cut down to its share.
+    pub order: MarketOrder,
+    /// What the order asked for.
+    pub requested: i64,
+}
+
+impl Allocation {
+    pub fn is_partial(&self) -> bool {
+        self.order.amount < self.requested
+    }
+}
+
+/// One `settleMarketOrders` solution and the queued orders it fills.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarketPlan {
+    pub chunk: MarketChunk,
+    pub bids: Vec<Allocation>,
+    pub asks: Vec<Allocation>,
+}
+
+impl MarketPlan {
+    pub fn to_solution(&self) -> Result<Solution> {
+        self.chunk.to_solution()
+    }
+
+    /// Applies the plan once its block has been built: the mirror and balances take the
+    /// fills, and the queue keeps whatever the allocations left over. `queue` must be the
+    /// one the plan was made from, unchanged except for orders pushed at the back. If an
+    /// allocation's order is no longer at its position, the queue is left as it is and an
+    /// error returned; the mirror and balances take the fills regardless.
+    pub fn apply(&self, queue: &mut MarketQueue, orderbook: &mut OrderBook, balances: &mut Balances) -> Result<()> {
+        self.chunk.apply(orderbook, balances);
+        for (side, allocations) in [(&queue.bids, &self.bids), (&queue.asks, &self.asks)] {
+            for allocation in allocations {
+                let queued = side.get(allocation.position);
+                let planned = MarketOrder { amount: allocation.requested, ..allocation.order };
+                if queued != Some(&planned) {
+                    return Err(SolverError::Validation(format!(
+                        "market order {:?} is no longer at position {} of the queue",
+                        planned, allocation.position
+                    )));
+                }
+            }
+        }
+        for (side, allocations) in [(&mut queue.bids, &self.bids), (&mut queue.asks, &self.asks)] {
+            for allocation in allocations {
+                side[allocation.position].amount -= allocation.order.amount;
+            }
+            side.retain(|order| order.amount > 0);
+        }
+        Ok(())
+    }
+}
+
+/// Splits `volume` over `amounts` in proportion to their size, rounding down and handing
+/// the lots lost to rounding to the oldest orders first. If there is enough volume for
+/// everything, every order gets its full amount.
+pub fn pro_rata(amounts: &[i64], volume: i64) -> Vec<i64> {
+    let demand: i128 = amounts.iter().map(|&amount| amount as i128).sum();
+    if demand <= volume as i128 {
+        return amounts.to_vec();
+    }
+    let mut shares: Vec<i64> = amounts
+        .iter()
+        .map(|&amount| (amount as i128 * volume as i128 / demand) as i64)
+        .collect();
+    let mut left = volume - shares.iter().sum::<i64>();
+    for (share, &amount) in shares.iter_mut().zip(amounts) {
+        if left == 0 {
+            break;
+        }
+        if *share < amount {
+            *share += 1;
+            left -= 1;
+        }
+    }
+    shares
+}
+
+// The front of one side of the book, up to the first order whose owner is already taken.
+fn resting_slice(orderbook: &OrderBook, is_bid: bool, size: usize, taken: &mut HashSet<[Word; 4]>) -> Vec<Order> {
+    orderbook
+        .chain(is_bid)
+        .into_iter()
+        .take(size)
+        .take_while(|order| taken.insert(order.addr))
+        .cloned()
+        .collect()
+}
+
+// The oldest queued orders whose owners are not taken yet, with their queue positions.
+fn batch(queue: &VecDeque<MarketOrder>, size: usize, taken: &mut HashSet<[Word; 4]>) -> Vec<(usize, MarketOrder)> {
+    queue
+        .iter()
+        .copied()
+        .enumerate()
+        .filter(|(_, order)| order.amount > 0 && taken.insert(order.addr))
+        .take(size)
+        .collect()
+}
+
+// Shares the depth of `resting` out over `batch` and fills that much of `resting`.
+fn allocate(batch: &[(usize, MarketOrder)], resting: &[Order]) -> Result<Option<(Vec<Allocation>, Vec<Fill>)>> {
+    let demand = batch.iter().try_fold(0, |total, (_, order)| add(total, order.amount))?;
+    let depth = resting.iter().try_fold(0, |total, order| add(total, order.max_amnt))?;
+    let volume = demand.min(depth);
+    if volume == 0 {
+        return Ok(None);
+    }
+
+    let amounts: Vec<i64> = batch.iter().map(|(_, order)| order.amount).collect();
+    let allocations = batch
+        .iter()
+        .zip(pro_rata(&amounts, volume))
+        .filter(|(_, share)| *share > 0)
+        .map(|(&(position, order), share)| Allocation {
+            position,
+            order: MarketOrder { amount: share, ..order },
+            requested: order.amount,
+        })
+        .collect();
+
+    let mut fills = vec![];
+    let mut remaining = volume;
+    for order in resting {
+        if remaining == 0 {
+            break;
+        }
+        let amount = remaining.min(order.max_amnt);
+        fills.push(Fill { order: order.clone(), amount });
+        remaining -= amount;
+    }
+    Ok(Some((allocations, fills)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn market(addr: Word, amount: i64) -> MarketOrder {
+        MarketOrder { amount, addr: [addr; 4], auth: [addr; 4] }
+    }
+
+    #[test]
+    fn pro_rata_hands_rounding_to_the_oldest() {
+        assert_eq!(pro_rata(&[5, 3], 10), vec![5, 3]);
+        // 10 of 15: 6.67, 2.67 and 0.67 round down to 6, 2 and 0, the two lots left go to
+        // the first two orders
+        assert_eq!(pro_rata(&[10, 4, 1], 10), vec![7, 3, 0]);
+    }
+
+    #[test]
+    fn plans_against_the_front_of_the_book() {
+        let mut orderbook = OrderBook::new();
+        let mut balances = Balances::new();
+        for (index, price, is_bid) in [(1, 100, true), (2, 99, true), (3, 101, false)] {
+            let addr = [index; 4];
+            orderbook.insert(Order { index, max_amnt: 4, price, is_bid, addr, auth: addr });
+            balances.set(addr, 10_000, 100);
+        }
+        let mut queue = MarketQueue::new();
+        // asks for 6 of the 8 resting bid lots, bids for 8 of the 4 resting ask lots
+        queue.push(market(10, 6), false);
+        queue.push(market(11, 6), true);
+        queue.push(market(12, 2), true);
+        // same owner as resting bid 1, waits for the next round
+        queue.push(market(1, 1), true);
+        for addr in 10..=12 {
+            balances.set([addr; 4], 10_000, 100);
+        }
+
+        let plan = queue.plan(&orderbook, &balances, [99; 4]).unwrap().unwrap();
+        assert_eq!(plan.bids.iter().map(|a| a.order.amount).collect::<Vec<_>>(), vec![3, 1]);
+        assert_eq!(plan.asks[0].order.amount, 6);
+        assert_eq!(plan.chunk.partial_amount_bid, 2);
+        // 4 * 100 + 2 * 99 over 6 lots
+        assert_eq!(plan.chunk.average_price_bids, 99);
+        assert_eq!(plan.chunk.average_price_asks, 101);
+
+        // a queue that lost an order since planning is left alone
+        let mut stale = queue.clone();
+        stale.bids.pop_front();
+        assert!(plan.apply(&mut stale, &mut orderbook.clone(), &mut balances.clone()).is_err());
+        assert_eq!(stale.bids.len(), 2);
+
+        plan.apply(&mut queue, &mut orderbook, &mut balances).unwrap();
+        assert!(queue.asks.is_empty());
+        assert_eq!(queue.bids, VecDeque::from([market(11, 3), market(12, 1), market(1, 1)]));
+        assert_eq!(orderbook.get(2, true).unwrap().max_amnt, 2);
+        assert_eq!(balances.get_1(&[11; 4]), 103);
+    }
+}
@@ -0,0 +1,75 @@
+//! Fallible wrappers around the `essential_app_utils` builder and node calls.
+use crate::batch::pack;
+use crate::diagnostics::SubmissionLog;
+use crate::error::{Result, SolverError};
+use crate::variant::ContractVariant;
+use essential_app_utils as utils;
+use essential_app_utils::db::Dbs;
+use essential_types::{ContentAddress, Key, Word, solution::{Solution, SolutionSet}};
+use std::fmt::Debug;
+
+/// Hands a solution set to the builder.
+pub async fn submit(dbs: &Dbs, solution_set: SolutionSet) -> Result<()> {
+    utils::builder::submit(&dbs.builder, solution_set)
+        .await
+        .map_err(|e| SolverError::Submit(e.to_string()))?;
+    Ok(())
+}
+
+/// Checks a solution set against the node's current state without building a block.
+pub async fn validate(dbs: &Dbs, solution_set: SolutionSet) -> Result<impl Debug> {
+    utils::node::validate_solution(&dbs.node, solution_set)
+        .await
+        .map_err(|e| SolverError::Validation(e.to_string()))
+}
+
+/// Builds a block from everything submitted so far. Fails if any solution set was rejected.
+pub async fn build(dbs: &Dbs) -> Result<()> {
+    build_logged(dbs, &SubmissionLog::default()).await
+}
+
+/// Like [`build`], but failed solution sets found in `log` are reported with their intent
+/// and decoded solutions.
+pub async fn build_logged(dbs: &Dbs, log: &SubmissionLog) -> Result<()> {
+    let o = utils::builder::build_default(dbs)
+        .await
+        .map_err(|e| SolverError::Build(e.to_string()))?;
+    if !o.failed.is_empty() {
+        let report: String = log.explain(&o.failed).iter().map(|set| set.to_string()).collect();
+        return Err(SolverError::BlockFailed(report));
+    }
+    Ok(())
+}
+
+/// Submits `solution_set` and builds a block with it.
+pub async fn submit_and_build(dbs: &Dbs, solution_set: SolutionSet) -> Result<()> {
+    submit_intent(dbs, &ContractVariant::standard(), "solution set", solution_set).await
+}
+
+/// Submits `solution_set` on behalf of `intent` and builds a block with it. A failure names
+/// the intent and decodes the solutions involved against `contract`.
+pub async fn submit_intent(dbs: &Dbs, contract: &ContractVariant, intent: &str, solution_set: SolutionSet) -> Result<()> {
+    let mut log = SubmissionLog::new(contract.clone());
+    log.record(intent, &solution_set);
+    submit(dbs, solution_set).await?;
+    build_logged(dbs, &log).await
+}
+
+/// Submits `solutions` in order, packed into as few blocks as their key conflicts allow
+/// with at most `max_solutions` per block, and returns the number of blocks built. Stops at
+/// the first block that fails.
+pub async fn submit_batched(dbs: &Dbs, intent: &str, solutions: Vec<Solution>, max_solutions: usize) -> Result<usize> {
+    let sets = pack(solutions, max_solutions)?;
+    let blocks = sets.len();
+    for (i, set) in sets.into_iter().enumerate() {
+        submit_intent(dbs, &ContractVariant::standard(), &format!("{} (block {} of {})", intent, i + 1, blocks), set).await?;
+    }
+    Ok(blocks)
+}
+
+/// Reads one storage slot of `contract` at the head of the chain.
+pub async fn query(dbs: &Dbs, contract: &ContentAddress, key: &Key) -> Result<Option<Vec<Word>>> {
+    utils::node::query_state_head(&dbs.node, contract, key)
+        .await
+        .map_err(|e| SolverError::Query(e.to_string()))
+}
@@ -0,0 +1,334 @@
+//! Off-chain checks of settle solutions before they are submitted.
+//!
+//! [`check_settle`] and [`check_market_settle`] re-implement the constraints of the
+//! `settle` and `settleMarketOrders` predicates against the mirror, so a chunk that the
+//! contract would reject is caught with a readable reason instead of a failed block.
+//! Both expect the mirror and balances as they are *before* the chunk is applied.
+use crate::book::{Balances, OrderBook};
+use crate::error::Result;
+use crate::fixed::{add, notional, sub};
+use crate::settlement::{MarketChunk, MarketLeg, SettleChunk, SettleLeg};
+use essential_types::Word;
+use std::collections::HashSet;
+use std::fmt;
+
+/// One predicate constraint the solution would break.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Short name of the constraint, e.g. `"next_key"` or `"token0 conservation"`.
+    pub constraint: &'static str,
+    pub detail: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.constraint, self.detail)
+    }
+}
+
+/// Checks a `settle` chunk. An empty list means the predicate should accept it.
+pub fn check_settle(orderbook: &OrderBook, balances: &Balances, chunk: &SettleChunk) -> Vec<Violation> {
+    let mut violations = vec![];
+    if let Err(e) = settle_constraints(orderbook, balances, chunk, &mut violations) {
+        violations.push(Violation { constraint: "overflow", detail: e.to_string() });
+    }
+    violations
+}
+
+/// Checks a `settleMarketOrders` chunk. An empty list means the predicate should accept it.
+pub fn check_market_settle(orderbook: &OrderBook, balances: &Balances, chunk: &MarketChunk) -> Vec<Violation> {
+    let mut violations = vec![];
+    if let Err(e) = market_constraints(orderbook, balances, chunk, &mut violations) {
+        violations.push(Violation { constraint: "overflow", detail: e.to_string() });
+    }
+    violations
+}
+
+fn settle_constraints(
+    orderbook: &OrderBook,
+    balances: &Balances,
+    chunk: &SettleChunk,
+    violations: &mut Vec<Violation>,
+) -> Result<()> {
+    let [s0, s1] = &chunk.solver_orders;
+    let mut seen = HashSet::from([s0.addr]);
+    let (bid0, bid1) = check_side(orderbook, balances, &chunk.bids, chunk.partial_amount_bid, chunk.first_bid_order, true, &mut seen, violations)?;
+    let (ask0, ask1) = check_side(orderbook, balances, &chunk.asks, chunk.partial_amount_ask, chunk.first_ask_order, false, &mut seen, violations)?;
+
+    // the solver may not take out more of either token than the orders put in
+    let token0 = add(sub(sub(ask0, bid0)?, notional(s0.max_amnt, s0.price)?)?, notional(s1.max_amnt, s1.price)?)?;
+    if token0 > 0 {
+        violations.push(Violation { constraint: "token0 conservation", detail: format!("solver gains {} token0 more than it pays", token0) });
+    }
+    let token1 = sub(add(sub(bid1, ask1)?, s0.max_amnt)?, s1.max_amnt)?;
+    if token1 > 0 {
+        violations.push(Violation { constraint: "token1 conservation", detail: format!("solver gains {} token1 more than it pays", token1) });
+    }
+
+    if s0.addr != s1.addr {
+        violations.push(Violation { constraint: "solver address", detail: format!("solver orders from {:?} and {:?}", s0.addr, s1.addr) });
+    }
+    let expected_0 = add(sub(balances.get_0(&s0.addr), notional(s0.max_amnt, s0.price)?)?, notional(s1.max_amnt, s1.price)?)?;
+    let expected_1 = sub(add(balances.get_1(&s0.addr), s0.max_amnt)?, s1.max_amnt)?;
+    if (chunk.solver_amount_0_final, chunk.solver_amount_1_final) != (expected_0, expected_1) {
+        violations.push(Violation {
+            constraint: "solver balance",
+            detail: format!(
+                "final ({}, {}) but the solver orders give ({}, {})",
+                chunk.solver_amount_0_final, chunk.solver_amount_1_final, expected_0, expected_1
+            ),
+        });
+    }
+    if chunk.solver_amount_0_final < 0 || chunk.solver_amount_1_final < 0 {
+        violations.push(Violation {
+            constraint: "solver balance non-negative",
+            detail: format!("final ({}, {})", chunk.solver_amount_0_final, chunk.solver_amount_1_final),
+        });
+    }
+    Ok(())
+}
+
+fn market_constraints(
+    orderbook: &OrderBook,
+    balances: &Balances,
+    chunk: &MarketChunk,
+    violations: &mut Vec<Violation>,
+) -> Result<()> {
+    let [s0, s1] = &chunk.solver_orders;
+    let mut seen = HashSet::from([s0.addr]);
+    let (bid0, bid1) = check_side(orderbook, balances, &chunk.bids, chunk.partial_amount_bid, chunk.first_bid_order, true, &mut seen, violations)?;
+    let (ask0, ask1) = check_side(orderbook, balances, &chunk.asks, chunk.partial_amount_ask, chunk.first_ask_order, false, &mut seen, violations)?;
+    if s0.addr != s1.addr {
+        violations.push(Violation { constraint: "solver address", detail: format!("solver orders from {:?} and {:?}", s0.addr, s1.addr) });
+    }
+    if bid1 == 0 || ask1 == 0 {
+        violations.push(Violation { constraint: "average price", detail: "both sides need resting fills".to_string() });
+        return Ok(());
+    }
+
+    for (average, token0, token1, side) in [(chunk.average_price_bids, bid0, bid1, "bid"), (chunk.average_price_asks, ask0, ask1, "ask")] {
+        if average != token0 / token1 {
+            violations.push(Violation {
+                constraint: "average price",
+                detail: format!("{} average {} but the fills give {}", side, average, token0 / token1),
+            });
+        }
+    }
+
+    // market asks are taken by the resting bids and market bids by the resting asks
+    let volume = |legs: &[MarketLeg]| legs.iter().try_fold(0, |total, leg| add(total, leg.order.amount));
+    for (market, resting, side) in [(volume(&chunk.ask_market)?, bid1, "ask"), (volume(&chunk.bid_market)?, ask1, "bid")] {
+        if market != resting {
+            violations.push(Violation {
+                constraint: "market volume",
+                detail: format!("{} market orders total {} but the resting fills total {}", side, market, resting),
+            });
+        }
+    }
+
+    for (legs, is_bid) in [(&chunk.bid_market, true), (&chunk.ask_market, false)] {
+        for leg in legs {
+            let order = &leg.order;
+            if !seen.insert(order.addr) {
+                violations.push(Violation { constraint: "duplicate address", detail: format!("{:?} appears twice", order.addr) });
+            }
+            let (expected_0, expected_1) = if is_bid {
+                (sub(balances.get_0(&order.addr), notional(order.amount, chunk.average_price_asks)?)?, add(balances.get_1(&order.addr), order.amount)?)
+            } else {
+                (add(balances.get_0(&order.addr), notional(order.amount, chunk.average_price_bids)?)?, sub(balances.get_1(&order.addr), order.amount)?)
+            };
+            if (leg.amount_0_final, leg.amount_1_final) != (expected_0, expected_1) {
+                violations.push(Violation {
+                    constraint: "market balance",
+                    detail: format!(
+                        "{} market order of {:?}: final ({}, {}) but expected ({}, {})",
+                        side(is_bid), order.addr, leg.amount_0_final, leg.amount_1_final, expected_0, expected_1
+                    ),
+                });
+            }
+            let spent = if is_bid { leg.amount_0_final } else { leg.amount_1_final };
+            if spent < 0 {
+                violations.push(Violation {
+                    constraint: "balance non-negative",
+                    detail: format!("{} market order of {:?} leaves {}", side(is_bid), order.addr, spent),
+                });
+            }
+        }
+    }
+
+    // the solver keeps the token0 the rounded bid average leaves and pays what the rounded
+    // ask average leaves short
+    let kept = sub(bid0, notional(bid1, chunk.average_price_bids)?)?;
+    let owed = sub(ask0, notional(ask1, chunk.average_price_asks)?)?;
+    let expected_0 = sub(add(balances.get_0(&s0.addr), kept)?, owed)?;
+    let expected_1 = balances.get_1(&s0.addr);
+    if (chunk.solver_amount_0_final, chunk.solver_amount_1_final) != (expected_0, expected_1) {
+        violations.push(Violation {
+            constraint: "solver balance",
+            detail: format!(
+                "final ({}, {}) but the average prices give ({}, {})",
+                chunk.solver_amount_0_final, chunk.solver_amount_1_final, expected_0, expected_1
+            ),
+        });
+    }
+    if chunk.solver_amount_0_final < 0 || chunk.solver_amount_1_final < 0 {
+        violations.push(Violation {
+            constraint: "solver balance non-negative",
+            detail: format!("final ({}, {})", chunk.solver_amount_0_final, chunk.solver_amount_1_final),
+        });
+    }
+    Ok(())
+}
+
+// Checks the resting orders of one side and returns the token0 and token1 they exchange,
+// computed the way `@verify_bids`/`@verify_asks` sum them.
+#[allow(clippy::too_many_arguments)]
+fn check_side(
+    orderbook: &OrderBook,
+    balances: &Balances,
+    legs: &[SettleLeg],
+    partial_amount: i64,
+    first_order: i64,
+    is_bid: bool,
+    seen: &mut HashSet<[Word; 4]>,
+    violations: &mut Vec<Violation>,
+) -> Result<(i64, i64)> {
+    let (mut token0, mut token1) = (0, 0);
+    let mut expected_first = orderbook.chain(is_bid).first().map_or(0, |o| o.index);
+    let mut previous: Option<i64> = None;
+
+    for (i, leg) in legs.iter().enumerate() {
+        let index = leg.order.index;
+        let expected_index = match previous {
+            Some(previous) => orderbook.next_index(previous, is_bid),
+            None => expected_first,
+        };
+        if index != expected_index {
+            let constraint = if previous.is_some() { "next_key" } else { "first order" };
+            violations.push(Violation {
+                constraint,
+                detail: format!("{} {} is settled where {} is linked", side(is_bid), index, expected_index),
+            });
+        }
+        previous = Some(index);
+
+        let Some(order) = orderbook.get(index, is_bid) else {
+            violations.push(Violation { constraint: "order exists", detail: format!("{} {} is not in the book", side(is_bid), index) });
+            continue;
+        };
+        if leg.order.auth != order.auth {
+            violations.push(Violation { constraint: "auth", detail: format!("{} {} signed by the wrong key", side(is_bid), index) });
+        }
+        if leg.addr != order.addr {
+            violations.push(Violation { constraint: "address", detail: format!("{} {} pays out to {:?} instead of its owner", side(is_bid), index, leg.addr) });
+        }
+        if !seen.insert(leg.addr) {
+            violations.push(Violation { constraint: "duplicate address", detail: format!("{:?} appears twice", leg.addr) });
+        }
+
+        let is_last = i + 1 == legs.len();
+        let amount = if is_last { partial_amount } else { order.max_amnt };
+        if is_last && (amount <= 0 || amount > order.max_amnt) {
+            violations.push(Violation {
+                constraint: "partial amount",
+                detail: format!("{} {} fills {} of {}", side(is_bid), index, amount, order.max_amnt),
+            });
+        }
+
+        let value = notional(amount, order.price)?;
+        token0 = add(token0, value)?;
+        token1 = add(token1, amount)?;
+        let (expected_0, expected_1) = if is_bid {
+            (sub(balances.get_0(&order.addr), value)?, add(balances.get_1(&order.addr), amount)?)
+        } else {
+            (add(balances.get_0(&order.addr), value)?, sub(balances.get_1(&order.addr), amount)?)
+        };
+        if (leg.amount_0_final, leg.amount_1_final) != (expected_0, expected_1) {
+            violations.push(Violation {
+                constraint: "balance",
+                detail: format!(
+                    "{} {}: final ({}, {}) but expected ({}, {})",
+                    side(is_bid), index, leg.amount_0_final, leg.amount_1_final, expected_0, expected_1
+                ),
+            });
+        }
+        let spent = if is_bid { leg.amount_0_final } else { leg.amount_1_final };
+        if spent < 0 {
+            violations.push(Violation { constraint: "balance non-negative", detail: format!("{} {} leaves {}", side(is_bid), index, spent) });
+        }
+
+        let next_key = orderbook.next_index(index, is_bid);
+        expected_first = next_key;
+        if is_last && amount < order.max_amnt {
+            expected_first = index;
+            let remaining = &leg.final_order;
+            let expected = (order.max_amnt - amount, order.price, order.addr, order.auth, next_key);
+            if (remaining.max_amnt, remaining.price, remaining.addr, remaining.auth, remaining.next_key) != expected {
+                violations.push(Violation {
+                    constraint: "partial order",
+                    detail: format!("{} {} is left as {:?}", side(is_bid), index, remaining),
+                });
+            }
+        }
+    }
+
+    if first_order != expected_first {
+        violations.push(Violation {
+            constraint: "first order",
+            detail: format!("first_{}_order becomes {} instead of {}", side(is_bid), first_order, expected_first),
+        });
+    }
+    Ok((token0, token1))
+}
+
+fn side(is_bid: bool) -> &'static str {
+    if is_bid { "bid" } else { "ask" }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::Order;
+    use crate::settlement::{Fill, plan_settle};
+
+    fn book() -> (OrderBook, Balances, [Word; 4]) {
+        let solver = [9; 4];
+        let mut orderbook = OrderBook::new();
+        let mut balances = Balances::new();
+        for (index, price, is_bid, addr) in [(1, 105, true, [1; 4]), (2, 104, true, [2; 4]), (3, 100, false, [3; 4])] {
+            orderbook.insert(Order { index, max_amnt: 10, price, is_bid, addr, auth: addr });
+            balances.set(addr, 10_000, 100);
+        }
+        balances.set(solver, 10_000, 100);
+        (orderbook, balances, solver)
+    }
+
+    #[test]
+    fn planned_chunk_passes() {
+        let (orderbook, balances, solver) = book();
+        let bids = vec![
+            Fill { order: orderbook.get(1, true).unwrap().clone(), amount: 10 },
+            Fill { order: orderbook.get(2, true).unwrap().clone(), amount: 5 },
+        ];
+        let asks = vec![Fill { order: orderbook.get(3, false).unwrap().clone(), amount: 10 }];
+        let plan = plan_settle(&orderbook, &balances, &bids, &asks, solver).unwrap();
+        let violations = check_settle(&orderbook, &balances, &plan.chunks[0]);
+        assert!(violations.is_empty(), "{:?}", violations);
+    }
+
+    #[test]
+    fn reports_tampered_chunk() {
+        let (orderbook, balances, solver) = book();
+        let bids = vec![Fill { order: orderbook.get(1, true).unwrap().clone(), amount: 10 }];
+        let asks = vec![Fill { order: orderbook.get(3, false).unwrap().clone(), amount: 10 }];
+        let mut chunk = plan_settle(&orderbook, &balances, &bids, &asks, solver).unwrap().chunks.remove(0);
+        // skip over order 1 and let the solver keep more token0 than it earned
+        chunk.bids[0].order.index = 2;
+        chunk.solver_orders[1].price += 1;
+
+        let constraints: Vec<&str> = check_settle(&orderbook, &balances, &chunk).iter().map(|v| v.constraint).collect();
+        assert!(constraints.contains(&"first order"), "{:?}", constraints);
+        assert!(constraints.contains(&"token0 conservation"), "{:?}", constraints);
+        assert!(constraints.contains(&"solver balance"), "{:?}", constraints);
+    }
+}
@@ -0,0 +1,136 @@
+//! Checks that the off-chain mirror still matches contract storage.
+//!
+//! Every order and balance the mirror tracks is read back with `query_state_head`, and
+//! both linked lists are walked to find orders the mirror does not know about.
+use crate::book::{Balances, Order, OrderBook};
+use crate::error::Result;
+use crate::loader::{load_order, load_side};
+use crate::state::StateReader;
+use essential_types::Word;
+use std::collections::HashSet;
+use std::fmt;
+
+/// One difference between the mirror and the chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Drift {
+    Balance0 { addr: [Word; 4], mirror: i64, chain: i64 },
+    Balance1 { addr: [Word; 4], mirror: i64, chain: i64 },
+    FirstOrder { is_bid: bool, mirror: i64, chain: i64 },
+    /// The mirror holds an order whose slot is empty on chain.
+    MissingOrder { is_bid: bool, index: i64 },
+    /// The chain links an order the mirror does not hold.
+    UnexpectedOrder { is_bid: bool, index: i64 },
+    /// Same slot, different contents.
+    OrderMismatch { mirror: Order, chain: Order },
+    NextKey { is_bid: bool, index: i64, mirror: i64, chain: i64 },
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let side = |is_bid: bool| if is_bid { "bid" } else { "ask" };
+        match self {
+            Drift::Balance0 { addr, mirror, chain } => {
+                write!(f, "balances_0[{:?}]: mirror {} != chain {}", addr, mirror, chain)
+            }
+            Drift::Balance1 { addr, mirror, chain } => {
+                write!(f, "balances_1[{:?}]: mirror {} != chain {}", addr, mirror, chain)
+            }
+            Drift::FirstOrder { is_bid, mirror, chain } => {
+                write!(f, "first_{}_order: mirror {} != chain {}", side(*is_bid), mirror, chain)
+            }
+            Drift::MissingOrder { is_bid, index } => {
+                write!(f, "{} {} is in the mirror but not on chain", side(*is_bid), index)
+            }
+            Drift::UnexpectedOrder { is_bid, index } => {
+                write!(f, "{} {} is on chain but not in the mirror", side(*is_bid), index)
+            }
+            Drift::OrderMismatch { mirror, chain } => {
+                write!(f, "order {}: mirror {:?} != chain {:?}", mirror.index, mirror, chain)
+            }
+            Drift::NextKey { is_bid, index, mirror, chain } => {
+                write!(f, "{} {} next_key: mirror {} != chain {}", side(*is_bid), index, mirror, chain)
+            }
+        }
+    }
+}
+
+/// Everything that differs. An empty report means the mirror is exact.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub drift: Vec<Drift>,
+}
+
+impl Report {
+    pub fn is_consistent(&self) -> bool {
+        self.drift.is_empty()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.drift.is_empty() {
+            return write!(f, "mirror matches chain");
+        }
+        writeln!(f, "{} differences between mirror and chain:", self.drift.len())?;
+        for drift in &self.drift {
+            writeln!(f, "  {}", drift)?;
+        }
+        Ok(())
+    }
+}
+
+/// Diffs `orderbook` and `balances` against contract state.
+///
+/// Use `assert!(report.is_consistent(), "{}", report)` in tests.
+pub async fn reconcile(
+    state: &StateReader<'_>,
+    orderbook: &OrderBook,
+    balances: &Balances,
+) -> Result<Report> {
+    let mut report = Report::default();
+
+    for is_bid in [true, false] {
+        let mirror_first = orderbook.chain(is_bid).first().map_or(0, |o| o.index);
+        let chain_first = if is_bid { state.first_bid().await? } else { state.first_ask().await? };
+        if mirror_first != chain_first {
+            report.drift.push(Drift::FirstOrder { is_bid, mirror: mirror_first, chain: chain_first });
+        }
+
+        for order in orderbook.chain(is_bid) {
+            match load_order(state, order.index, is_bid).await? {
+                None => report.drift.push(Drift::MissingOrder { is_bid, index: order.index }),
+                Some((chain_order, chain_next)) => {
+                    if &chain_order != order {
+                        report.drift.push(Drift::OrderMismatch { mirror: order.clone(), chain: chain_order });
+                    }
+                    let mirror_next = orderbook.next_index(order.index, is_bid);
+                    if mirror_next != chain_next {
+                        report.drift.push(Drift::NextKey { is_bid, index: order.index, mirror: mirror_next, chain: chain_next });
+                    }
+                }
+            }
+        }
+
+        let tracked: HashSet<i64> = orderbook.chain(is_bid).iter().map(|o| o.index).collect();
+        for order in load_side(state, is_bid).await? {
+            if !tracked.contains(&order.index) {
+                report.drift.push(Drift::UnexpectedOrder { is_bid, index: order.index });
+            }
+        }
+    }
+
+    let addrs: HashSet<&[Word; 4]> = balances.balance_0.keys().chain(balances.balance_1.keys()).collect();
+    for addr in addrs {
+        let chain_0 = state.balance0(*addr).await?;
+        let chain_1 = state.balance1(*addr).await?;
+        let (mirror_0, mirror_1) = (balances.get_0(addr), balances.get_1(addr));
+        if mirror_0 != chain_0 {
+            report.drift.push(Drift::Balance0 { addr: *addr, mirror: mirror_0, chain: chain_0 });
+        }
+        if mirror_1 != chain_1 {
+            report.drift.push(Drift::Balance1 { addr: *addr, mirror: mirror_1, chain: chain_1 });
+        }
+    }
+
+    Ok(report)
+}
@@ -0,0 +1,210 @@
+//! Replays a recorded orderbook trace against a deployed contract.
+//!
+//! Every `delta in bids/asks` entry of a trace step is read as the new total size of that
+//! price level, 0 meaning the level is gone. Growing a level adds one order at its back,
+//! shrinking it cancels orders from the back, so the oldest liquidity keeps its priority.
+//! Once a step's deltas are on chain, anything that crosses is settled against the solver
+//! and the on-chain book is compared level by level with the trace's snapshot.
+use crate::book::{Balances, Order, OrderBook};
+use crate::error::{Result, SolverError};
+use crate::fixed::add;
+use crate::ids::OrderIdAllocator;
+use crate::loader::load_orderbook;
+use crate::matching::match_book;
+use crate::node;
+use crate::settlement::plan_settle_with;
+use crate::solutions::produce_solution_deposit;
+use crate::state::StateReader;
+use crate::strategy::Vwap;
+use crate::trace::OrderBookParse;
+use crate::variant::ContractVariant;
+use essential_app_utils::db::Dbs;
+use essential_types::{Word, solution::{Solution, SolutionSet}};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A price level whose on-chain size differs from the trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevelMismatch {
+    pub is_bid: bool,
+    pub price: u64,
+    pub expected: i64,
+    pub on_chain: i64,
+}
+
+/// What one step did to the chain.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StepReport {
+    pub adds: usize,
+    pub cancels: usize,
+    /// Token1 settled on each side because the book crossed.
+    pub settled: i64,
+    pub blocks: usize,
+    pub mismatches: Vec<LevelMismatch>,
+}
+
+/// Drives a trace through the contract, one block per solution.
+pub struct Replay<'a> {
+    dbs: &'a Dbs,
+    variant: ContractVariant,
+    pub orderbook: OrderBook,
+    pub balances: Balances,
+    /// Expected size of every price level, as given by the trace so far.
+    pub snapshot_bids: BTreeMap<u64, i64>,
+    pub snapshot_asks: BTreeMap<u64, i64>,
+    makers: Vec<[Word; 4]>,
+    next_maker: usize,
+    solver_addr: [Word; 4],
+    ids: OrderIdAllocator,
+}
+
+impl<'a> Replay<'a> {
+    /// `makers` own the replayed liquidity and are used round robin, so a settle chunk can
+    /// hold as many orders as there are makers. All of them and the solver must be funded
+    /// with [`Replay::fund`] before the first step. `variant` is the deployed contract,
+    /// whose batch size the settlements are planned for.
+    pub fn new(dbs: &'a Dbs, variant: ContractVariant, makers: Vec<[Word; 4]>, solver_addr: [Word; 4]) -> Result<Self> {
+        if makers.is_empty() {
+            return Err(SolverError::Validation("replay needs at least one maker".to_string()));
+        }
+        Ok(Self {
+            dbs,
+            variant,
+            orderbook: OrderBook::new(),
+            balances: Balances::new(),
+            snapshot_bids: BTreeMap::new(),
+            snapshot_asks: BTreeMap::new(),
+            makers,
+            next_maker: 0,
+            solver_addr,
+            ids: OrderIdAllocator::default(),
+        })
+    }
+
+    /// Deposits `amount0`/`amount1` for every maker and the solver.
+    pub async fn fund(&mut self, amount0: i64, amount1: i64) -> Result<()> {
+        let addrs: Vec<[Word; 4]> = self.makers.iter().copied().chain([self.solver_addr]).collect();
+        for addr in addrs {
+            let final0 = add(self.balances.get_0(&addr), amount0)?;
+            let final1 = add(self.balances.get_1(&addr), amount1)?;
+            self.submit(produce_solution_deposit(amount0, final0, amount1, final1, addr, addr, addr)).await?;
+            self.balances.set(addr, final0, final1);
+        }
+        Ok(())
+    }
+
+    /// Replays every step and returns one report per step.
+    pub async fn run(&mut self, steps: &[OrderBookParse]) -> Result<Vec<StepReport>> {
+        let mut reports = vec![];
+        for step in steps {
+            reports.push(self.step(step).await?);
+        }
+        Ok(reports)
+    }
+
+    /// Applies one step's deltas, settles whatever crosses and checks the result.
+    pub async fn step(&mut self, step: &OrderBookParse) -> Result<StepReport> {
+        let mut report = StepReport::default();
+        for (deltas, is_bid) in [(&step.bids, true), (&step.asks, false)] {
+            for (&price, &size) in deltas {
+                self.resize_level(is_bid, price, size, &mut report).await?;
+                let snapshot = if is_bid { &mut self.snapshot_bids } else { &mut self.snapshot_asks };
+                if size == 0 {
+                    snapshot.remove(&price);
+                } else {
+                    snapshot.insert(price, size);
+                }
+            }
+        }
+
+        let fills = match_book(&self.orderbook);
+        if !fills.is_empty() {
+            let plan = plan_settle_with(&self.orderbook, &self.balances, &fills.bids, &fills.asks, self.solver_addr, &Vwap, self.variant.batch)?;
+            for chunk in &plan.chunks {
+                self.submit(chunk.to_solution_for(&self.variant)?).await?;
+                chunk.apply(&mut self.orderbook, &mut self.balances);
+                let settled = chunk.bids.iter().chain(&chunk.asks).map(|leg| leg.order.index);
+                self.ids.reclaim(&self.orderbook, &self.state(), settled).await?;
+                report.blocks += 1;
+            }
+            report.settled = fills.bid_volume();
+        }
+
+        report.mismatches = self.check().await?;
+        Ok(report)
+    }
+
+    /// Compares the on-chain book with the snapshot. Levels that crossed and were settled
+    /// show up here too, since the trace itself never settles anything.
+    pub async fn check(&self) -> Result<Vec<LevelMismatch>> {
+        let on_chain = load_orderbook(&self.state()).await?;
+        let mut mismatches = vec![];
+        for (snapshot, is_bid) in [(&self.snapshot_bids, true), (&self.snapshot_asks, false)] {
+            let levels = level_sizes(&on_chain, is_bid);
+            let prices: BTreeSet<u64> = snapshot.keys().chain(levels.keys()).copied().collect();
+            for price in prices {
+                let expected = snapshot.get(&price).copied().unwrap_or(0);
+                let on_chain = levels.get(&price).copied().unwrap_or(0);
+                if expected != on_chain {
+                    mismatches.push(LevelMismatch { is_bid, price, expected, on_chain });
+                }
+            }
+        }
+        Ok(mismatches)
+    }
+
+    // Brings the mirror's level at `price` to `size`, one block per add or cancel.
+    async fn resize_level(&mut self, is_bid: bool, price: u64, size: i64, report: &mut StepReport) -> Result<()> {
+        let mut current = level_sizes(&self.orderbook, is_bid).get(&price).copied().unwrap_or(0);
+        while current > size {
+            let newest = self.orderbook.side(is_bid)[&price].back().map(|o| o.index).unwrap_or(0);
+            let Some(plan) = self.orderbook.plan_cancel(newest, is_bid) else { break };
+            if let Err(e) = self.submit(plan.to_solution()).await {
+                plan.undo(&mut self.orderbook);
+                return Err(e);
+            }
+            self.ids.release(newest);
+            current -= plan.order.max_amnt;
+            report.cancels += 1;
+            report.blocks += 1;
+        }
+        if current < size {
+            let addr = self.makers[self.next_maker % self.makers.len()];
+            self.next_maker += 1;
+            let index = self.ids.next_free(&self.orderbook, &self.state(), addr).await?;
+            let order = Order { index, max_amnt: size - current, price: price as i64, is_bid, addr, auth: addr };
+            let plan = match self.orderbook.plan_insert(order) {
+                Ok(plan) => plan,
+                Err(e) => {
+                    self.ids.release(index);
+                    return Err(e);
+                }
+            };
+            if let Err(e) = self.submit(plan.to_solution()).await {
+                self.ids.release(index);
+                return Err(e);
+            }
+            plan.apply(&mut self.orderbook);
+            report.adds += 1;
+            report.blocks += 1;
+        }
+        Ok(())
+    }
+
+    fn state(&self) -> StateReader<'a> {
+        StateReader::new(self.dbs, self.variant.contract.clone())
+    }
+
+    // The builders address the standard contract, so every solution is retargeted first.
+    async fn submit(&self, solution: Solution) -> Result<()> {
+        let solution = self.variant.retarget(solution)?;
+        node::submit_and_build(self.dbs, SolutionSet { solutions: vec![solution] }).await
+    }
+}
+
+fn level_sizes(orderbook: &OrderBook, is_bid: bool) -> BTreeMap<u64, i64> {
+    orderbook
+        .side(is_bid)
+        .iter()
+        .map(|(&price, level)| (price, level.iter().map(|o| o.max_amnt).sum()))
+        .collect()
+}
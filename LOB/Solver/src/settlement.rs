@@ -0,0 +1,778 @@
+//! Settlement of an arbitrary number of fills, and of market orders.
+//!
+//! The `settle` predicate takes at most 10 bids and 10 asks, so larger batches have to be
+//! split into several solutions that are applied one after the other. Each chunk settles a
+//! prefix of what is left of the linked lists and hands `first_bid_order`/`first_ask_order`
+//! over to the next chunk.
+use crate::book::{Balances, Order, OrderBook};
+use crate::error::{Result, SolverError};
+use crate::fixed::{add, notional, sub};
+use crate::solutions::{
+    LimitOrder, MarketOrder, SettleOrder, produce_solution_market_order_batch, produce_solution_settle_batch,
+};
+use crate::strategy::{SolverStrategy, Vwap};
+use crate::variant::ContractVariant;
+use essential_types::{Word, solution::Solution};
+use std::collections::HashSet;
+
+/// Number of bids and asks the standard contract's `settle` predicate accepts per solution.
+pub const SETTLE_BATCH: usize = 10;
+
+/// A resting order and the amount of it that gets filled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fill {
+    pub order: Order,
+    pub amount: i64,
+}
+
+impl Fill {
+    pub fn is_partial(&self) -> bool {
+        self.amount < self.order.max_amnt
+    }
+}
+
+/// One order of a chunk together with the state it leaves behind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SettleLeg {
+    pub order: SettleOrder,
+    pub addr: [Word; 4],
+    pub amount_0_final: i64,
+    pub amount_1_final: i64,
+    /// What is left of the order, all zero if it was filled in full.
+    pub final_order: LimitOrder,
+}
+
+/// Everything needed to build one `settle` solution.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SettleChunk {
+    pub bids: Vec<SettleLeg>,
+    pub asks: Vec<SettleLeg>,
+    pub partial_amount_bid: i64,
+    pub partial_amount_ask: i64,
+    pub solver_orders: [LimitOrder; 2],
+    pub solver_amount_0_final: i64,
+    pub solver_amount_1_final: i64,
+    pub first_bid_order: i64,
+    pub first_ask_order: i64,
+    /// Balances of the chunk's addresses before it, which its solution starts from.
+    pub balances: Balances,
+    /// Orders per side of the contract the chunk is planned for.
+    pub batch: usize,
+}
+
+impl SettleChunk {
+    /// The partially filled order is always the last one of each side.
+    pub fn partial_bid_index(&self) -> i64 {
+        self.bids.len().saturating_sub(1) as i64
+    }
+
+    pub fn partial_ask_index(&self) -> i64 {
+        self.asks.len().saturating_sub(1) as i64
+    }
+
+    /// Applies the chunk to the mirror, once its block has been built.
+    pub fn apply(&self, orderbook: &mut OrderBook, balances: &mut Balances) {
+        for (legs, is_bid) in [(&self.bids, true), (&self.asks, false)] {
+            for leg in legs {
+                balances.set(leg.addr, leg.amount_0_final, leg.amount_1_final);
+                let filled = orderbook
+                    .get(leg.order.index, is_bid)
+                    .map_or(0, |order| order.max_amnt - leg.final_order.max_amnt);
+                orderbook.fill(leg.order.index, is_bid, filled);
+            }
+        }
+        balances.set(self.solver_orders[0].addr, self.solver_amount_0_final, self.solver_amount_1_final);
+    }
+
+    /// Builds the solution for the standard contract, which only fits a chunk planned for
+    /// [`SETTLE_BATCH`] orders per side.
+    pub fn to_solution(&self) -> Result<Solution> {
+        self.to_solution_for(&ContractVariant::standard())
+    }
+
+    /// Builds the solution for `variant`'s `settle`, padded to the chunk's batch size. Fails
+    /// if `variant` takes a different number of orders per side.
+    pub fn to_solution_for(&self, variant: &ContractVariant) -> Result<Solution> {
+        check_width(self.batch, variant)?;
+        let p = self.padded()?;
+        produce_solution_settle_batch(
+            variant,
+            self.partial_amount_bid,
+            self.partial_amount_ask,
+            self.partial_bid_index(),
+            self.partial_ask_index(),
+            &p.bid_orders,
+            &p.ask_orders,
+            self.solver_orders,
+            &self.balances,
+            &p.address_list_bid,
+            &p.address_list_ask,
+            &p.amount_0_final_bid,
+            &p.amount_1_final_bid,
+            &p.amount_0_final_ask,
+            &p.amount_1_final_ask,
+            self.first_bid_order,
+            self.first_ask_order,
+            &p.final_bid_order,
+            &p.final_ask_order,
+        )
+    }
+
+    fn padded(&self) -> Result<Padded> {
+        let mut p = Padded::new(&self.bids, &self.asks, self.batch)?;
+        p.solver(self.solver_orders[0].addr, self.solver_amount_0_final, self.solver_amount_1_final);
+        Ok(p)
+    }
+}
+
+/// Chunks that clear a whole batch, and the balances once all of them are applied.
+#[derive(Debug, Clone)]
+pub struct SettlePlan {
+    pub chunks: Vec<SettleChunk>,
+    pub balances: Balances,
+    /// Orders per side of the contract the plan is made for.
+    pub batch: usize,
+}
+
+impl SettlePlan {
+    /// The solutions for the standard contract in the order they have to be applied, one
+    /// block each. Fails unless the plan was made for [`SETTLE_BATCH`] orders per side.
+    pub fn solutions(&self) -> Result<Vec<Solution>> {
+        self.chunks.iter().map(SettleChunk::to_solution).collect()
+    }
+
+    /// [`Self::solutions`] for `variant`, which has to take the plan's batch size.
+    pub fn solutions_for(&self, variant: &ContractVariant) -> Result<Vec<Solution>> {
+        self.chunks.iter().map(|chunk| chunk.to_solution_for(variant)).collect()
+    }
+}
+
+/// A market order together with the balances it leaves behind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarketLeg {
+    pub order: MarketOrder,
+    pub amount_0_final: i64,
+    pub amount_1_final: i64,
+}
+
+/// Everything needed to build one `settleMarketOrders` solution.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarketChunk {
+    /// Resting bids that take the market asks.
+    pub bids: Vec<SettleLeg>,
+    /// Resting asks that take the market bids.
+    pub asks: Vec<SettleLeg>,
+    pub partial_amount_bid: i64,
+    pub partial_amount_ask: i64,
+    pub bid_market: Vec<MarketLeg>,
+    pub ask_market: Vec<MarketLeg>,
+    pub average_price_bids: i64,
+    pub average_price_asks: i64,
+    pub solver_orders: [LimitOrder; 2],
+    /// The solver's balances after taking up what the rounded average prices leave.
+    pub solver_amount_0_final: i64,
+    pub solver_amount_1_final: i64,
+    pub first_bid_order: i64,
+    pub first_ask_order: i64,
+    /// Balances of the chunk's addresses before it, which its solution starts from.
+    pub balances: Balances,
+    /// Orders per side of the contract the chunk is planned for.
+    pub batch: usize,
+}
+
+impl MarketChunk {
+    pub fn partial_bid_index(&self) -> i64 {
+        self.bids.len().saturating_sub(1) as i64
+    }
+
+    pub fn partial_ask_index(&self) -> i64 {
+        self.asks.len().saturating_sub(1) as i64
+    }
+
+    /// Applies the chunk to the mirror, once its block has been built.
+    pub fn apply(&self, orderbook: &mut OrderBook, balances: &mut Balances) {
+        for (legs, is_bid) in [(&self.bids, true), (&self.asks, false)] {
+            for leg in legs {
+                balances.set(leg.addr, leg.amount_0_final, leg.amount_1_final);
+                let filled = orderbook
+                    .get(leg.order.index, is_bid)
+                    .map_or(0, |order| order.max_amnt - leg.final_order.max_amnt);
+                orderbook.fill(leg.order.index, is_bid, filled);
+            }
+        }
+        for leg in self.bid_market.iter().chain(&self.ask_market) {
+            balances.set(leg.order.addr, leg.amount_0_final, leg.amount_1_final);
+        }
+        balances.set(self.solver_orders[0].addr, self.solver_amount_0_final, self.solver_amount_1_final);
+    }
+
+    /// Builds the solution for the standard contract, which only fits a chunk planned for
+    /// [`SETTLE_BATCH`] orders per side.
+    pub fn to_solution(&self) -> Result<Solution> {
+        self.to_solution_for(&ContractVariant::standard())
+    }
+
+    /// Builds the solution for `variant`'s `settleMarketOrders`, padded to the chunk's batch
+    /// size. Fails if `variant` takes a different number of orders per side.
+    pub fn to_solution_for(&self, variant: &ContractVariant) -> Result<Solution> {
+        check_width(self.batch, variant)?;
+        if self.bid_market.len().max(self.ask_market.len()) > self.batch {
+            return Err(SolverError::Validation(format!("market orders do not fit a batch of {}", self.batch)));
+        }
+        let p = self.padded()?;
+        let m = PaddedMarket::new(&self.bid_market, &self.ask_market, self.batch);
+        produce_solution_market_order_batch(
+            variant,
+            self.partial_amount_bid,
+            self.partial_amount_ask,
+            self.partial_bid_index(),
+            self.partial_ask_index(),
+            &p.bid_orders,
+            &p.ask_orders,
+            &m.bid_market_orders,
+            &m.ask_market_orders,
+            self.average_price_bids,
+            self.average_price_asks,
+            self.solver_orders,
+            &self.balances,
+            &p.address_list_bid,
+            &p.address_list_ask,
+            &m.address_list_bid_market,
+            &m.address_list_ask_market,
+            &p.amount_0_final_bid,
+            &p.amount_1_final_bid,
+            &p.amount_0_final_ask,
+            &p.amount_1_final_ask,
+            &m.amount_0_final_bid_market,
+            &m.amount_1_final_bid_market,
+            &m.amount_0_final_ask_market,
+            &m.amount_1_final_ask_market,
+            self.first_bid_order,
+            self.first_ask_order,
+            &p.final_bid_order,
+            &p.final_ask_order,
+        )
+    }
+
+    fn padded(&self) -> Result<Padded> {
+        let mut p = Padded::new(&self.bids, &self.asks, self.batch)?;
+        p.solver(self.solver_orders[0].addr, self.solver_amount_0_final, self.solver_amount_1_final);
+        Ok(p)
+    }
+}
+
+// The resting order part of a settle solution, padded with zeroes to `batch` orders and
+// `batch + 1` addresses per side.
+struct Padded {
+    bid_orders: Vec<SettleOrder>,
+    ask_orders: Vec<SettleOrder>,
+    address_list_bid: Vec<[Word; 4]>,
+    address_list_ask: Vec<[Word; 4]>,
+    amount_0_final_bid: Vec<i64>,
+    amount_1_final_bid: Vec<i64>,
+    amount_0_final_ask: Vec<i64>,
+    amount_1_final_ask: Vec<i64>,
+    final_bid_order: Vec<LimitOrder>,
+    final_ask_order: Vec<LimitOrder>,
+}
+
+impl Padded {
+    fn new(bids: &[SettleLeg], asks: &[SettleLeg], batch: usize) -> Result<Self> {
+        if bids.len().max(asks.len()) > batch {
+            return Err(SolverError::Validation(format!("chunk does not fit a batch of {}", batch)));
+        }
+        let zero = [0; 4];
+        let empty = LimitOrder { max_amnt: 0, price: 0, is_bid: false, addr: zero, auth: zero, next_key: 0 };
+        let mut p = Padded {
+            bid_orders: vec![SettleOrder { index: 0, auth: zero }; batch],
+            ask_orders: vec![SettleOrder { index: 0, auth: zero }; batch],
+            address_list_bid: vec![zero; batch + 1],
+            address_list_ask: vec![zero; batch + 1],
+            amount_0_final_bid: vec![0; batch + 1],
+            amount_1_final_bid: vec![0; batch + 1],
+            amount_0_final_ask: vec![0; batch + 1],
+            amount_1_final_ask: vec![0; batch + 1],
+            final_bid_order: vec![empty; batch],
+            final_ask_order: vec![empty; batch],
+        };
+        for (i, leg) in bids.iter().enumerate() {
+            p.bid_orders[i] = leg.order;
+            p.address_list_bid[i] = leg.addr;
+            p.amount_0_final_bid[i] = leg.amount_0_final;
+            p.amount_1_final_bid[i] = leg.amount_1_final;
+            p.final_bid_order[i] = leg.final_order;
+        }
+        for (i, leg) in asks.iter().enumerate() {
+            p.ask_orders[i] = leg.order;
+            p.address_list_ask[i] = leg.addr;
+            p.amount_0_final_ask[i] = leg.amount_0_final;
+            p.amount_1_final_ask[i] = leg.amount_1_final;
+            p.final_ask_order[i] = leg.final_order;
+        }
+        Ok(p)
+    }
+
+    // the last slot of both address lists is the solver
+    fn solver(&mut self, addr: [Word; 4], amount_0_final: i64, amount_1_final: i64) {
+        let last = self.address_list_bid.len() - 1;
+        self.address_list_bid[last] = addr;
+        self.address_list_ask[last] = addr;
+        self.amount_0_final_bid[last] = amount_0_final;
+        self.amount_0_final_ask[last] = amount_0_final;
+        self.amount_1_final_bid[last] = amount_1_final;
+        self.amount_1_final_ask[last] = amount_1_final;
+    }
+}
+
+// The market order part of a settleMarketOrders solution, padded to `batch` orders.
+struct PaddedMarket {
+    bid_market_orders: Vec<MarketOrder>,
+    ask_market_orders: Vec<MarketOrder>,
+    address_list_bid_market: Vec<[Word; 4]>,
+    address_list_ask_market: Vec<[Word; 4]>,
+    amount_0_final_bid_market: Vec<i64>,
+    amount_1_final_bid_market: Vec<i64>,
+    amount_0_final_ask_market: Vec<i64>,
+    amount_1_final_ask_market: Vec<i64>,
+}
+
+impl PaddedMarket {
+    fn new(bids: &[MarketLeg], asks: &[MarketLeg], batch: usize) -> Self {
+        let zero = [0; 4];
+        let no_order = MarketOrder { amount: 0, addr: zero, auth: zero };
+        let mut m = PaddedMarket {
+            bid_market_orders: vec![no_order; batch],
+            ask_market_orders: vec![no_order; batch],
+            address_list_bid_market: vec![zero; batch],
+            address_list_ask_market: vec![zero; batch],
+            amount_0_final_bid_market: vec![0; batch],
+            amount_1_final_bid_market: vec![0; batch],
+            amount_0_final_ask_market: vec![0; batch],
+            amount_1_final_ask_market: vec![0; batch],
+        };
+        for (i, leg) in bids.iter().enumerate() {
+            m.bid_market_orders[i] = leg.order;
+            m.address_list_bid_market[i] = leg.order.addr;
+            m.amount_0_final_bid_market[i] = leg.amount_0_final;
+            m.amount_1_final_bid_market[i] = leg.amount_1_final;
+        }
+        for (i, leg) in asks.iter().enumerate() {
+            m.ask_market_orders[i] = leg.order;
+            m.address_list_ask_market[i] = leg.order.addr;
+            m.amount_0_final_ask_market[i] = leg.amount_0_final;
+            m.amount_1_final_ask_market[i] = leg.amount_1_final;
+        }
+        m
+    }
+}
+
+fn check_width(batch: usize, variant: &ContractVariant) -> Result<()> {
+    if variant.batch != batch {
+        return Err(SolverError::Validation(format!(
+            "chunk is planned for a batch of {}, the contract takes {}",
+            batch, variant.batch
+        )));
+    }
+    Ok(())
+}
+
+/// Plans the settlement of `bids` and `asks` against the solver.
+///
+/// Both lists must be prefixes of their side's linked list in `orderbook`, in priority
+/// order. Only the last fill of each list may be partial. A chunk never contains the same
+/// address twice, because the predicate constrains each order's balances independently.
+///
+/// All balance and notional maths is checked. A chunk is cut short when its token0 total
+/// would no longer fit in an `i64`; anything that overflows on its own is an error.
+///
+/// The solver orders are priced with [`Vwap`], see [`plan_settle_with`] for other
+/// strategies.
+pub fn plan_settle(
+    orderbook: &OrderBook,
+    balances: &Balances,
+    bids: &[Fill],
+    asks: &[Fill],
+    solver_addr: [Word; 4],
+) -> Result<SettlePlan> {
+    plan_settle_with(orderbook, balances, bids, asks, solver_addr, &Vwap, SETTLE_BATCH)
+}
+
+/// Like [`plan_settle`], with every chunk's solver orders chosen by `strategy` and at most
+/// `batch` orders per side in a chunk, for a [`ContractVariant`] of that size.
+pub fn plan_settle_with(
+    orderbook: &OrderBook,
+    balances: &Balances,
+    bids: &[Fill],
+    asks: &[Fill],
+    solver_addr: [Word; 4],
+    strategy: &dyn SolverStrategy,
+    batch: usize,
+) -> Result<SettlePlan> {
+    let mut balances = balances.clone();
+    let mut chunks = vec![];
+    let (mut next_bid, mut next_ask) = (0, 0);
+
+    while next_bid < bids.len() || next_ask < asks.len() {
+        let mut seen = HashSet::from([solver_addr]);
+        let bid_end = chunk_end(bids, next_bid, batch, &mut seen, true)?;
+        let ask_end = chunk_end(asks, next_ask, batch, &mut seen, bid_end == next_bid)?;
+        let (bid_slice, ask_slice) = (&bids[next_bid..bid_end], &asks[next_ask..ask_end]);
+        next_bid = bid_end;
+        next_ask = ask_end;
+        let before = chunk_balances(&balances, bid_slice.iter().chain(ask_slice).map(|fill| fill.order.addr), solver_addr);
+
+        let (chunk_bids, _, _) = settle_side(orderbook, &mut balances, bid_slice)?;
+        let (chunk_asks, _, _) = settle_side(orderbook, &mut balances, ask_slice)?;
+
+        let solver_orders = strategy.solver_orders(
+            solver_addr,
+            bid_slice,
+            ask_slice,
+            balances.get_0(&solver_addr),
+            balances.get_1(&solver_addr),
+        )?;
+        let solver_amount_0_final = add(
+            sub(balances.get_0(&solver_addr), notional(solver_orders[0].max_amnt, solver_orders[0].price)?)?,
+            notional(solver_orders[1].max_amnt, solver_orders[1].price)?,
+        )?;
+        let solver_amount_1_final = sub(
+            add(balances.get_1(&solver_addr), solver_orders[0].max_amnt)?,
+            solver_orders[1].max_amnt,
+        )?;
+        balances.set(solver_addr, solver_amount_0_final, solver_amount_1_final);
+
+        chunks.push(SettleChunk {
+            partial_amount_bid: bid_slice.last().map_or(0, |fill| fill.amount),
+            partial_amount_ask: ask_slice.last().map_or(0, |fill| fill.amount),
+            first_bid_order: first_order_after(orderbook, bids, next_bid, true),
+            first_ask_order: first_order_after(orderbook, asks, next_ask, false),
+            bids: chunk_bids,
+            asks: chunk_asks,
+            solver_orders,
+            solver_amount_0_final,
+            solver_amount_1_final,
+            balances: before,
+            batch,
+        });
+    }
+
+    Ok(SettlePlan { chunks, balances, batch })
+}
+
+/// Plans one `settleMarketOrders` solution: market asks are filled by the resting
+/// `bid_fills` and market bids by the resting `ask_fills`, each at the average price of
+/// the resting orders they trade with.
+///
+/// Both market sides must be non-empty and fit `batch`, the orders per side of the
+/// contract the chunk is built for, [`SETTLE_BATCH`] for the standard one, and the fills
+/// of each side must add up to the market orders of the other side, since the contract
+/// requires exactly that.
+///
+/// Market orders trade at the average prices rounded down, so the market asks receive a
+/// little less token0 than the limit bids pay and the market bids pay a little less than
+/// the limit asks receive. The solver keeps the first difference and pays the second, so
+/// token0 is conserved across the whole solution; it must hold enough token0 to cover a
+/// net shortfall and may not take part in the trade itself.
+#[allow(clippy::too_many_arguments)]
+pub fn plan_market_settle(
+    orderbook: &OrderBook,
+    balances: &Balances,
+    bid_market: &[MarketOrder],
+    ask_market: &[MarketOrder],
+    bid_fills: &[Fill],
+    ask_fills: &[Fill],
+    solver_addr: [Word; 4],
+    batch: usize,
+) -> Result<MarketChunk> {
+    let traders = bid_fills.iter().chain(ask_fills).map(|fill| fill.order.addr);
+    let before = chunk_balances(balances, traders.chain(bid_market.iter().chain(ask_market).map(|order| order.addr)), solver_addr);
+    let mut balances = balances.clone();
+    let (bids, bid_amount, bid_token0) = settle_side(orderbook, &mut balances, bid_fills)?;
+    let (asks, ask_amount, ask_token0) = settle_side(orderbook, &mut balances, ask_fills)?;
+    if bid_amount == 0 || ask_amount == 0 {
+        return Err(SolverError::Validation("market settlement needs resting orders on both sides".to_string()));
+    }
+    let average_price_bids = bid_token0 / bid_amount;
+    let average_price_asks = ask_token0 / ask_amount;
+
+    // market bids pay the average ask price, market asks receive the average bid price
+    let mut market_legs = |orders: &[MarketOrder], is_bid: bool| -> Result<Vec<MarketLeg>> {
+        orders
+            .iter()
+            .map(|order| {
+                let (amount_0_final, amount_1_final) = if is_bid {
+                    (
+                        sub(balances.get_0(&order.addr), notional(order.amount, average_price_asks)?)?,
+                        add(balances.get_1(&order.addr), order.amount)?,
+                    )
+                } else {
+                    (
+                        add(balances.get_0(&order.addr), notional(order.amount, average_price_bids)?)?,
+                        sub(balances.get_1(&order.addr), order.amount)?,
+                    )
+                };
+                balances.set(order.addr, amount_0_final, amount_1_final);
+                Ok(MarketLeg { order: *order, amount_0_final, amount_1_final })
+            })
+            .collect()
+    };
+    let bid_market = market_legs(bid_market, true)?;
+    let ask_market = market_legs(ask_market, false)?;
+
+    let traders = bids.iter().chain(&asks).map(|leg| leg.addr);
+    if traders.chain(bid_market.iter().chain(&ask_market).map(|leg| leg.order.addr)).any(|addr| addr == solver_addr) {
+        return Err(SolverError::Validation("the solver cannot trade in its own market settlement".to_string()));
+    }
+    let kept = sub(bid_token0, notional(bid_amount, average_price_bids)?)?;
+    let owed = sub(ask_token0, notional(ask_amount, average_price_asks)?)?;
+    let solver_amount_0_final = sub(add(balances.get_0(&solver_addr), kept)?, owed)?;
+    let solver_amount_1_final = balances.get_1(&solver_addr);
+    if solver_amount_0_final < 0 {
+        return Err(SolverError::Validation(format!(
+            "solver token0 balance would end at {} after covering the average price rounding",
+            solver_amount_0_final
+        )));
+    }
+
+    Ok(MarketChunk {
+        partial_amount_bid: bid_fills.last().map_or(0, |fill| fill.amount),
+        partial_amount_ask: ask_fills.last().map_or(0, |fill| fill.amount),
+        first_bid_order: first_order_after(orderbook, bid_fills, bid_fills.len(), true),
+        first_ask_order: first_order_after(orderbook, ask_fills, ask_fills.len(), false),
+        bids,
+        asks,
+        bid_market,
+        ask_market,
+        average_price_bids,
+        average_price_asks,
+        solver_orders: [
+            LimitOrder { max_amnt: 0, price: 0, is_bid: true, addr: solver_addr, auth: solver_addr, next_key: 0 },
+            LimitOrder { max_amnt: 0, price: 0, is_bid: false, addr: solver_addr, auth: solver_addr, next_key: 0 },
+        ],
+        solver_amount_0_final,
+        solver_amount_1_final,
+        balances: before,
+        batch,
+    })
+}
+
+// The entries of `balances` for a chunk's addresses and the solver.
+fn chunk_balances(balances: &Balances, addrs: impl IntoIterator<Item = [Word; 4]>, solver_addr: [Word; 4]) -> Balances {
+    let mut before = Balances::new();
+    for addr in addrs.into_iter().chain([solver_addr]) {
+        before.set(addr, balances.get_0(&addr), balances.get_1(&addr));
+    }
+    before
+}
+
+// Legs for one side's fills, updating `balances` as it goes. Also returns the token1 and
+// token0 totals of the side.
+fn settle_side(orderbook: &OrderBook, balances: &mut Balances, fills: &[Fill]) -> Result<(Vec<SettleLeg>, i64, i64)> {
+    let mut legs = vec![];
+    let (mut amount, mut token0) = (0, 0);
+    for fill in fills {
+        let order = &fill.order;
+        let value = notional(fill.amount, order.price)?;
+        let (amount_0_final, amount_1_final) = if order.is_bid {
+            (sub(balances.get_0(&order.addr), value)?, add(balances.get_1(&order.addr), fill.amount)?)
+        } else {
+            (add(balances.get_0(&order.addr), value)?, sub(balances.get_1(&order.addr), fill.amount)?)
+        };
+        balances.set(order.addr, amount_0_final, amount_1_final);
+        amount = add(amount, fill.amount)?;
+        token0 = add(token0, value)?;
+        legs.push(settle_leg(orderbook, fill, amount_0_final, amount_1_final));
+    }
+    Ok((legs, amount, token0))
+}
+
+// End (exclusive) of the next chunk of `fills` starting at `start`. Stops at the batch size,
+// at the first address already used in the chunk, or once the chunk's token0 plus amount
+// total would overflow, which bounds the solver's rounded up notional too. With `force` at
+// least one fill is taken, so a chunk always makes progress.
+fn chunk_end(fills: &[Fill], start: usize, batch: usize, seen: &mut HashSet<[Word; 4]>, force: bool) -> Result<usize> {
+    let mut end = start;
+    let mut total: i64 = 0;
+    while end < fills.len() && end - start < batch {
+        let fill = &fills[end];
+        let forced = force && end == start;
+        let weight = add(notional(fill.amount, fill.order.price)?, fill.amount)?;
+        let Some(next_total) = total.checked_add(weight) else { break };
+        if !seen.insert(fill.order.addr) && !forced {
+            break;
+        }
+        total = next_total;
+        end += 1;
+    }
+    Ok(end)
+}
+
+// Value of `first_*_order` once every fill before `next` has been applied.
+fn first_order_after(orderbook: &OrderBook, fills: &[Fill], next: usize, is_bid: bool) -> i64 {
+    if let Some(fill) = fills.get(next) {
+        return fill.order.index;
+    }
+    match fills.last() {
+        Some(fill) if fill.is_partial() => fill.order.index,
+        _ => {
+            let settled: Vec<i64> = fills.iter().map(|fill| fill.order.index).collect();
+            orderbook.first_index_excluding(is_bid, &settled)
+        }
+    }
+}
+
+fn settle_leg(orderbook: &OrderBook, fill: &Fill, amount_0_final: i64, amount_1_final: i64) -> SettleLeg {
+    let order = &fill.order;
+    let final_order = if fill.is_partial() {
+        LimitOrder {
+            max_amnt: order.max_amnt - fill.amount,
+            price: order.price,
+            is_bid: order.is_bid,
+            addr: order.addr,
+            auth: order.auth,
+            next_key: orderbook.next_index(order.index, order.is_bid),
+        }
+    } else {
+        LimitOrder { max_amnt: 0, price: 0, is_bid: false, addr: [0; 4], auth: [0; 4], next_key: 0 }
+    };
+    SettleLeg {
+        order: SettleOrder { index: order.index, auth: order.auth },
+        addr: order.addr,
+        amount_0_final,
+        amount_1_final,
+        final_order,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(index: i64, price: i64, is_bid: bool) -> Order {
+        Order { index, max_amnt: 10, price, is_bid, addr: [index, 0, 0, 0], auth: [0; 4] }
+    }
+
+    #[test]
+    fn chunks_hand_over_first_order() {
+        let mut orderbook = OrderBook::new();
+        let mut balances = Balances::new();
+        for i in 1..=25 {
+            orderbook.insert(order(i, 100, true));
+            balances.set([i, 0, 0, 0], 10_000, 0);
+        }
+        balances.set([99, 0, 0, 0], 0, 1_000);
+        let mut bids: Vec<Fill> = (1..=23).map(|i| Fill { order: order(i, 100, true), amount: 10 }).collect();
+        bids.last_mut().unwrap().amount = 4;
+
+        let plan = plan_settle(&orderbook, &balances, &bids, &[], [99, 0, 0, 0]).unwrap();
+
+        assert_eq!(plan.chunks.len(), 3);
+        assert_eq!(plan.chunks[0].first_bid_order, 11);
+        assert_eq!(plan.chunks[1].first_bid_order, 21);
+        // the partially filled order stays at the front of the book
+        assert_eq!(plan.chunks[2].first_bid_order, 23);
+        assert_eq!(plan.chunks[2].partial_bid_index(), 2);
+        assert_eq!(plan.chunks[2].partial_amount_bid, 4);
+        assert_eq!(plan.chunks[2].bids[2].final_order.max_amnt, 6);
+        assert_eq!(plan.chunks[2].bids[2].final_order.next_key, 24);
+        assert_eq!(plan.balances.get_1(&[99, 0, 0, 0]), 1_000 - 224);
+    }
+
+    #[test]
+    fn splits_chunks_before_token0_overflows() {
+        let orderbook = OrderBook::new();
+        let mut balances = Balances::new();
+        balances.set([99, 0, 0, 0], i64::MAX / 2, 0);
+        let price = i64::MAX / 25;
+        let asks: Vec<Fill> = (1..=3)
+            .map(|i| {
+                balances.set([i, 0, 0, 0], 0, 10);
+                Fill { order: order(i, price, false), amount: 10 }
+            })
+            .collect();
+        // the solver passes on what it buys, so it can afford every chunk
+        let bids: Vec<Fill> = (11..=13)
+            .map(|i| {
+                balances.set([i, 0, 0, 0], i64::MAX, 0);
+                Fill { order: order(i, price, true), amount: 10 }
+            })
+            .collect();
+
+        // two fills are 20 * price, which still fits; a third one would not
+        let plan = plan_settle(&orderbook, &balances, &bids, &asks, [99, 0, 0, 0]).unwrap();
+        assert_eq!(plan.chunks.iter().map(|c| c.asks.len()).collect::<Vec<_>>(), vec![2, 1]);
+        assert_eq!(plan.chunks.iter().map(|c| c.bids.len()).collect::<Vec<_>>(), vec![2, 1]);
+        assert_eq!(plan.chunks[0].solver_orders[0].price, price + 1);
+
+        let too_big = [Fill { order: order(1, i64::MAX / 5, false), amount: 10 }];
+        assert!(matches!(
+            plan_settle(&orderbook, &balances, &[], &too_big, [99, 0, 0, 0]),
+            Err(SolverError::Overflow(_))
+        ));
+    }
+
+    #[test]
+    fn pads_chunks_to_the_plan_batch() {
+        let orderbook = OrderBook::new();
+        let mut balances = Balances::new();
+        balances.set([99, 0, 0, 0], 0, 1_000);
+        let bids: Vec<Fill> = (1..=5)
+            .map(|i| {
+                balances.set([i, 0, 0, 0], 10_000, 0);
+                Fill { order: order(i, 100, true), amount: 10 }
+            })
+            .collect();
+
+        let plan = plan_settle_with(&orderbook, &balances, &bids, &[], [99, 0, 0, 0], &Vwap, 3).unwrap();
+        assert_eq!(plan.chunks.iter().map(|c| (c.bids.len(), c.batch)).collect::<Vec<_>>(), vec![(3, 3), (2, 3)]);
+        // the standard contract settles 10 orders per side
+        assert!(matches!(plan.solutions(), Err(SolverError::Validation(_))));
+
+        let plan = plan_settle(&orderbook, &balances, &bids, &[], [99, 0, 0, 0]).unwrap();
+        let solutions = plan.solutions().unwrap();
+        // index and auth of every order slot
+        assert_eq!(solutions[0].predicate_data[4].len(), SETTLE_BATCH * 5);
+    }
+
+    #[test]
+    fn market_settlement_conserves_both_tokens() {
+        let mut orderbook = OrderBook::new();
+        let mut balances = Balances::new();
+        for (index, price, is_bid) in [(1, 101, true), (2, 100, true), (3, 103, false), (4, 104, false)] {
+            orderbook.insert(order(index, price, is_bid));
+        }
+        let solver = [99, 0, 0, 0];
+        let traders: Vec<[Word; 4]> = (1..=4).chain(10..=13).map(|i| [i, 0, 0, 0]).collect();
+        for addr in &traders {
+            balances.set(*addr, 10_000, 100);
+        }
+        let market = |addr: i64, amount: i64| MarketOrder { amount, addr: [addr, 0, 0, 0], auth: [0; 4] };
+        // 15 lots for 1510 average 100 and 13 lots for 1342 average 103, both rounded down
+        let bid_fills = [Fill { order: order(1, 101, true), amount: 10 }, Fill { order: order(2, 100, true), amount: 5 }];
+        let ask_fills = [Fill { order: order(3, 103, false), amount: 10 }, Fill { order: order(4, 104, false), amount: 3 }];
+        let bid_market = [market(12, 8), market(13, 5)];
+        let ask_market = [market(10, 9), market(11, 6)];
+
+        let total = |balances: &Balances| {
+            traders.iter().chain([&solver]).fold((0, 0), |(t0, t1), addr| (t0 + balances.get_0(addr), t1 + balances.get_1(addr)))
+        };
+        let before = total(&balances);
+        let chunk = plan_market_settle(&orderbook, &balances, &bid_market, &ask_market, &bid_fills, &ask_fills, solver, SETTLE_BATCH).unwrap();
+        assert!(crate::preflight::check_market_settle(&orderbook, &balances, &chunk).is_empty());
+        // keeps 1510 - 1500 of the bids and pays 1342 - 1339 of the asks
+        assert_eq!((chunk.solver_amount_0_final, chunk.solver_amount_1_final), (7, 0));
+        chunk.apply(&mut orderbook, &mut balances);
+        assert_eq!(total(&balances), before);
+
+        // a solver without token0 cannot make up the asks' rounding on its own
+        let balances = Balances::new();
+        let mut orderbook = OrderBook::new();
+        orderbook.insert(order(5, 100, true));
+        orderbook.insert(order(6, 103, false));
+        orderbook.insert(order(7, 104, false));
+        let bid_fills = [Fill { order: order(5, 100, true), amount: 10 }];
+        let ask_fills = [Fill { order: order(6, 103, false), amount: 10 }, Fill { order: order(7, 104, false), amount: 3 }];
+        let shortfall = plan_market_settle(&orderbook, &balances, &bid_market, &ask_market, &bid_fills, &ask_fills, solver, SETTLE_BATCH);
+        assert!(matches!(shortfall, Err(SolverError::Validation(_))));
+    }
+}
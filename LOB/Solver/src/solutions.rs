@@ -0,0 +1,746 @@
+//! Builders for every predicate of the orderbook contract.
+//!
+//! Each `produce_solution_*` function returns a single `Solution` with the predicate data
+//! and the storage mutations the predicate expects. The caller is responsible for passing
+//! values that are consistent with the current contract state.
+use crate::abi::{deposit, withdraw, addLimitOrderBid, addLimitOrderAsk, addLimitOrderBidBehind, addLimitOrderAskBehind, removeLimitOrderBid, removeLimitOrderAsk, storage};
+use crate::book::Balances;
+use crate::error::{Result, SolverError};
+use crate::keys::order_keys;
+use crate::variant::ContractVariant;
+use essential_types::{Value, Word, solution::{Solution, Mutation}};
+
+/// A limit order as stored in the contract's `bid_orders`/`ask_orders` maps.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LimitOrder {
+    /// Quantity in lots of token1.
+    pub max_amnt: i64,
+    /// Token0 base units per lot, see [`crate::fixed::Decimals`].
+    pub price: i64,
+    pub is_bid: bool,
+    pub addr: [Word; 4],
+    pub auth: [Word; 4],
+    /// Index of the next order in the linked list, 0 for the last order.
+    pub next_key: i64,
+}
+
+// A `LimitOrder` the way the ABI's predicate data takes it.
+type LimitOrderTuple = (i64, i64, bool, [Word; 4], [Word; 4], i64);
+
+/// Builds a `deposit` solution crediting `addr_word` with the given deltas.
+pub fn produce_solution_deposit(
+    amount_0_delta: i64,
+    amount_0_final: i64,
+    amount_1_delta: i64,
+    amount_1_final: i64,
+    addr_word: [Word; 4],
+    key_word: [Word; 4],
+    auth_word: [Word; 4]
+) -> Solution {
+    let depoit_data = deposit::Vars{
+        amount0: amount_0_delta,
+        amount1: amount_1_delta,
+        addr: addr_word,
+        key: key_word,
+        auth: auth_word,
+    };
+    let deposit_state_mutations: Vec<Mutation> = storage::mutations()
+    .balances_0(|map| map.entry(addr_word, amount_0_final))
+    .balances_1(|map| map.entry(addr_word, amount_1_final))
+    .into();
+
+    Solution {
+        predicate_to_solve: deposit::ADDRESS,
+        predicate_data: depoit_data.into(),
+        state_mutations: deposit_state_mutations,
+    }
+}
+
+/// Builds a `withdraw` solution debiting `addr_word` by the given deltas.
+pub fn produce_solution_withdraw(
+    amount_0_delta: i64,
+    amount_0_final: i64,
+    amount_1_delta: i64,
+    amount_1_final: i64,
+    addr_word: [Word; 4],
+    key_word: [Word; 4],
+    auth_word: [Word; 4]
+) -> Solution {
+    let withdraw_data = withdraw::Vars{
+        amount0: amount_0_delta,
+        amount1: amount_1_delta,
+        addr: addr_word,
+        key: key_word,
+        auth: auth_word,
+    };
+    let withdraw_state_mutations: Vec<Mutation> = storage::mutations()
+    .balances_0(|map| map.entry(addr_word, amount_0_final))
+    .balances_1(|map| map.entry(addr_word, amount_1_final))
+    .into();
+
+    Solution {
+        predicate_to_solve: withdraw::ADDRESS,
+        predicate_data: withdraw_data.into(),
+        state_mutations: withdraw_state_mutations,
+    }
+}
+
+//Below is implementation of the AddLimitOrder predicate solution
+/*
+Notes: 
+- the linked list points from leading_key -> new_index -> trailing_key
+- index 0 is assigned for nil orders. Therefore, the last order's next_key is 0
+- if the leading_key is 0, then the new order is the first order in the orderbook
+- if the leading_key is not 0, then the new order is inserted in the orderbook
+*/
+/// Builds an `addLimitOrderBid` solution inserting `new_order` between `leading_key` and `trailing_key`.
+/// Only a new first order, `leading_key` 0, moves the head to `first_order_index`; any other
+/// add solves `addLimitOrderBidBehind`, which leaves the head alone, so adds behind
+/// different orders of one side can share a set.
+pub fn produce_solution_add_limit_order_bid(
+    leading_key: i64,
+    trailing_key: i64,
+    new_order: LimitOrder,
+    new_index: i64,
+    leading_order_next: i64,
+    first_order_index: i64,
+) -> Solution {
+    // Convert the LimitOrder struct to the expected tuple format
+    let limit_order_tuple: LimitOrderTuple = (new_order.max_amnt, new_order.price, new_order.is_bid, new_order.addr, new_order.auth, new_order.next_key);
+    let (predicate_to_solve, predicate_data) = if leading_key == 0 {
+        (addLimitOrderBid::ADDRESS, addLimitOrderBid::Vars { leading_key, trailing_key, new_order: limit_order_tuple, new_index }.into())
+    } else {
+        (addLimitOrderBidBehind::ADDRESS, addLimitOrderBidBehind::Vars { leading_key, trailing_key, new_order: limit_order_tuple, new_index }.into())
+    };
+    let mut mutations = storage::mutations()
+    .bid_orders(|map| 
+        map.entry(new_index, |tup| 
+            tup.max_amnt(new_order.max_amnt)
+            .price(new_order.price)
+            .isBid(new_order.is_bid)
+            .addr(new_order.addr)
+            .auth(new_order.auth)
+            .next_key(new_order.next_key)
+        )
+    )
+    .bid_orders(|map| 
+        map.entry(leading_key, |tup| 
+            tup.next_key(leading_order_next)
+        )
+    );
+    if leading_key == 0 {
+        mutations = mutations.first_bid_order(first_order_index);
+    }
+
+    let add_limit_order_state_mutations: Vec<Mutation> = mutations.into();
+    
+    Solution {
+        predicate_to_solve,
+        predicate_data,
+        state_mutations: add_limit_order_state_mutations,
+    }
+}
+
+/// Builds a `removeLimitOrderBid` solution unlinking `middle_index` and zeroing its slot.
+pub fn produce_solution_remove_limit_order_bid(
+    leading_key: i64,
+    trailing_key: i64,
+    middle_index: i64,
+    leading_order_next: i64,
+    first_order_index: i64,
+) -> Solution {
+    let remove_limit_order_data = removeLimitOrderBid::Vars{
+        leading_key,
+        trailing_key,
+        middle_index,
+    };
+    let mutations = storage::mutations()
+    .bid_orders(|map| 
+        map.entry(middle_index, |tup| 
+            tup.max_amnt(0)
+            .price(0)
+            .isBid(false)
+            .addr([0,0,0,0])
+            .auth([0,0,0,0])
+            .next_key(0)
+        )
+    )
+    .bid_orders(|map| 
+        map.entry(leading_key, |tup| 
+            tup.next_key(leading_order_next)
+        )
+    )
+    .first_bid_order(first_order_index);
+    let remove_limit_order_state_mutations: Vec<Mutation> = mutations.into();
+    
+    Solution {
+        predicate_to_solve: removeLimitOrderBid::ADDRESS,
+        predicate_data: remove_limit_order_data.into(),
+        state_mutations: remove_limit_order_state_mutations,
+    }
+}
+
+/// Builds an `addLimitOrderAsk` solution inserting `new_order` between `leading_key` and `trailing_key`.
+/// Only a new first order, `leading_key` 0, moves the head to `first_order_index`; any other
+/// add solves `addLimitOrderAskBehind`, which leaves the head alone, so adds behind
+/// different orders of one side can share a set.
+pub fn produce_solution_add_limit_order_ask(
+    leading_key: i64,
+    trailing_key: i64,
+    new_order: LimitOrder,
+    new_index: i64,
+    leading_order_next: i64,
+    first_order_index: i64,
+) -> Solution {
+    // Convert the LimitOrder struct to the expected tuple format
+    let limit_order_tuple: LimitOrderTuple = (new_order.max_amnt, new_order.price, new_order.is_bid, new_order.addr, new_order.auth, new_order.next_key);
+    let (predicate_to_solve, predicate_data) = if leading_key == 0 {
+        (addLimitOrderAsk::ADDRESS, addLimitOrderAsk::Vars { leading_key, trailing_key, new_order: limit_order_tuple, new_index }.into())
+    } else {
+        (addLimitOrderAskBehind::ADDRESS, addLimitOrderAskBehind::Vars { leading_key, trailing_key, new_order: limit_order_tuple, new_index }.into())
+    };
+    let mut mutations = storage::mutations()
+    .ask_orders(|map| 
+        map.entry(new_index, |tup| 
+            tup.max_amnt(new_order.max_amnt)
+            .price(new_order.price)
+            .isBid(new_order.is_bid)
+            .addr(new_order.addr)
+            .auth(new_order.auth)
+            .next_key(new_order.next_key)
+        )
+    )
+    .ask_orders(|map| 
+        map.entry(leading_key, |tup| 
+            tup.next_key(leading_order_next)
+        )
+    );
+    if leading_key == 0 {
+        mutations = mutations.first_ask_order(first_order_index);
+    }
+
+    let add_limit_order_state_mutations: Vec<Mutation> = mutations.into();
+    
+    Solution {
+        predicate_to_solve,
+        predicate_data,
+        state_mutations: add_limit_order_state_mutations,
+    }
+}
+/// Builds a `removeLimitOrderAsk` solution unlinking `middle_index` and zeroing its slot.
+pub fn produce_solution_remove_limit_order_ask(
+    leading_key: i64,
+    trailing_key: i64,
+    middle_index: i64,
+    leading_order_next: i64,
+    first_order_index: i64,
+) -> Solution {
+    let remove_limit_order_data = removeLimitOrderAsk::Vars{
+        leading_key,
+        trailing_key,
+        middle_index,
+    };
+    let mutations = storage::mutations()
+    .ask_orders(|map| 
+        map.entry(middle_index, |tup| 
+            tup.max_amnt(0)
+            .price(0)
+            .isBid(false)
+            .addr([0,0,0,0])
+            .auth([0,0,0,0])
+            .next_key(0)
+        )
+    )
+    .ask_orders(|map| 
+        map.entry(leading_key, |tup| 
+            tup.next_key(leading_order_next)
+        )
+    )
+    .first_ask_order(first_order_index);
+    let remove_limit_order_state_mutations: Vec<Mutation> = mutations.into();
+
+    Solution {
+        predicate_to_solve: removeLimitOrderAsk::ADDRESS,
+        predicate_data: remove_limit_order_data.into(),
+        state_mutations: remove_limit_order_state_mutations,
+    }
+}
+
+/// Most new orders one `addLimitOrdersBid`/`addLimitOrdersAsk` solution of the standard
+/// contract links in.
+pub const INSERT_RUN: usize = 5;
+
+/// [`produce_solution_add_limit_orders_batch`] for the contract `crate::abi` was generated
+/// from, whose runs take [`INSERT_RUN`] orders.
+pub fn produce_solution_add_limit_orders(
+    is_bid: bool,
+    leading_key: i64,
+    trailing_key: i64,
+    first_order_index: i64,
+    new_orders: &[(i64, LimitOrder)],
+) -> Result<Solution> {
+    produce_solution_add_limit_orders_batch(&ContractVariant::standard(), is_bid, leading_key, trailing_key, first_order_index, new_orders)
+}
+
+/// Builds an `addLimitOrdersBid` or `addLimitOrdersAsk` solution linking a run of new
+/// orders, given as `(index, order)` in linked list order, between `leading_key` and
+/// `trailing_key`. Every order's `next_key` points at the next one and the last at
+/// `trailing_key`; the run is padded to `variant.insert_run` with index 0.
+///
+/// The predicate declares every slot of the run, so padding writes slot 0, with the zeros
+/// every other builder leaves there, and the predicate holds it unchanged.
+/// `first_order_index`, the head of the side once the run is in, is written either way.
+pub fn produce_solution_add_limit_orders_batch(
+    variant: &ContractVariant,
+    is_bid: bool,
+    leading_key: i64,
+    trailing_key: i64,
+    first_order_index: i64,
+    new_orders: &[(i64, LimitOrder)],
+) -> Result<Solution> {
+    let Some(&(first_index, _)) = new_orders.first() else {
+        return Err(SolverError::Validation("an order run needs at least one order".to_string()));
+    };
+    if new_orders.len() > variant.insert_run {
+        return Err(SolverError::Validation(format!(
+            "an order run has {} orders, the contract takes at most {}",
+            new_orders.len(),
+            variant.insert_run
+        )));
+    }
+
+    // same layout as addLimitOrdersBid::Vars: add_order is the order followed by its index
+    let empty = LimitOrder { max_amnt: 0, price: 0, is_bid: false, addr: [0; 4], auth: [0; 4], next_key: 0 };
+    let padding = std::iter::repeat_n((0, empty), variant.insert_run - new_orders.len());
+    let run: Vec<(i64, LimitOrder)> = new_orders.iter().copied().chain(padding).collect();
+    let predicate_data: Vec<Value> = vec![
+        vec![leading_key],
+        vec![trailing_key],
+        run.iter().flat_map(|(index, order)| limit_orders_value(&[*order]).into_iter().chain([*index])).collect(),
+    ];
+
+    // slot 0 holds no order, so a run at the front leaves its next_key at 0
+    let leading_order_next = if leading_key != 0 { first_index } else { 0 };
+    let mut mutations = storage::mutations();
+    for (index, order) in &run {
+        mutations = if is_bid {
+            mutations.bid_orders(|map| map.entry(*index, |tup|
+                tup.max_amnt(order.max_amnt)
+                .price(order.price)
+                .isBid(order.is_bid)
+                .addr(order.addr)
+                .auth(order.auth)
+                .next_key(order.next_key)
+            ))
+        } else {
+            mutations.ask_orders(|map| map.entry(*index, |tup|
+                tup.max_amnt(order.max_amnt)
+                .price(order.price)
+                .isBid(order.is_bid)
+                .addr(order.addr)
+                .auth(order.auth)
+                .next_key(order.next_key)
+            ))
+        };
+    }
+    mutations = if is_bid {
+        mutations
+            .bid_orders(|map| map.entry(leading_key, |tup| tup.next_key(leading_order_next)))
+            .first_bid_order(first_order_index)
+    } else {
+        mutations
+            .ask_orders(|map| map.entry(leading_key, |tup| tup.next_key(leading_order_next)))
+            .first_ask_order(first_order_index)
+    };
+    let state_mutations: Vec<Mutation> = mutations.into();
+
+    Ok(Solution {
+        predicate_to_solve: variant.predicate(if is_bid { "addLimitOrdersBid" } else { "addLimitOrdersAsk" })?,
+        predicate_data,
+        state_mutations,
+    })
+}
+
+/// Reference to a resting order that takes part in a settlement.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SettleOrder {
+    pub index: i64,
+    pub auth: [Word; 4],
+}
+//Below is implementation of the settle predicate solution
+/// Builds a `settle` solution. The orders in `bid_orders`/`ask_orders` must follow the
+/// on-chain linked list; unused slots are zero. The order at `partial_*_index` is filled
+/// by `partial_amount_*`, every order before it is filled in full.
+///
+/// Every slot holds the balances its own fill leaves behind, the way the predicate
+/// constrains it, so no trader may have more than one slot; `balances` gives the zero
+/// address's balances for the padding slots.
+///
+/// Built by [`produce_solution_settle_batch`] for the standard contract, so the slots of
+/// orders filled in full are deleted the same way.
+#[allow(clippy::too_many_arguments)]
+pub fn produce_solution_settle(
+    partial_amount_bid: i64,
+    partial_amount_ask: i64,
+    partial_bid_index: i64,
+    partial_ask_index: i64,
+    bid_orders: [SettleOrder; 10],
+    ask_orders: [SettleOrder; 10],
+    solver_orders: [LimitOrder; 2],
+    balances: &Balances, // balances before the settlement
+    address_list_bid: [[Word; 4]; 11], // the last index is the solver address
+    address_list_ask: [[Word; 4]; 11], // the last index is the solver address
+    amount_0_final_bid: [i64; 11],
+    amount_1_final_bid: [i64; 11],
+    amount_0_final_ask: [i64; 11],
+    amount_1_final_ask: [i64; 11],
+    first_bid_order: i64,
+    first_ask_order: i64,
+    final_bid_order: [LimitOrder; 10],
+    final_ask_order: [LimitOrder; 10],
+) -> Result<Solution> {
+    produce_solution_settle_batch(
+        &ContractVariant::standard(), partial_amount_bid, partial_amount_ask, partial_bid_index, partial_ask_index,
+        &bid_orders, &ask_orders, solver_orders, balances, &address_list_bid, &address_list_ask,
+        &amount_0_final_bid, &amount_1_final_bid, &amount_0_final_ask, &amount_1_final_ask,
+        first_bid_order, first_ask_order, &final_bid_order, &final_ask_order,
+    )
+}
+/// A market order, filled at the average price of the limit orders it consumes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MarketOrder {
+    pub amount: i64,
+    pub addr: [Word; 4],
+    pub auth: [Word; 4],
+}
+/// Builds a `settleMarketOrders` solution. Market bids are filled by `ask_orders` at
+/// `average_price_asks` and market asks by `bid_orders` at `average_price_bids`. The solver,
+/// in the last slot of the limit order address lists, takes up the token0 those rounded
+/// down prices leave over or short.
+///
+/// Built by [`produce_solution_market_order_batch`] for the standard contract, so the slots
+/// of orders filled in full are deleted the same way.
+#[allow(clippy::too_many_arguments)]
+pub fn produce_solution_market_order(
+    partial_amount_bid: i64,
+    partial_amount_ask: i64,
+    partial_bid_index: i64,
+    partial_ask_index: i64,
+    bid_orders: [SettleOrder; 10],
+    ask_orders: [SettleOrder; 10],
+    bid_market_orders: [MarketOrder; 10],
+    ask_market_orders: [MarketOrder; 10],
+    average_price_bids: i64,
+    average_price_asks: i64,
+    solver_orders: [LimitOrder; 2],
+    balances: &Balances, // balances before the settlement
+    address_list_bid: [[Word; 4]; 11], // the last index is the solver address
+    address_list_ask: [[Word; 4]; 11], // the last index is the solver address
+    address_list_bid_market: [[Word; 4]; 10],
+    address_list_ask_market: [[Word; 4]; 10],
+    amount_0_final_bid: [i64; 11],
+    amount_1_final_bid: [i64; 11],
+    amount_0_final_ask: [i64; 11],
+    amount_1_final_ask: [i64; 11],
+    amount_0_final_bid_market: [i64; 10],
+    amount_1_final_bid_market: [i64; 10],
+    amount_0_final_ask_market: [i64; 10],
+    amount_1_final_ask_market: [i64; 10],
+    first_bid_order: i64,
+    first_ask_order: i64,
+    final_bid_order: [LimitOrder; 10],
+    final_ask_order: [LimitOrder; 10],
+) -> Result<Solution> {
+    produce_solution_market_order_batch(
+        &ContractVariant::standard(), partial_amount_bid, partial_amount_ask, partial_bid_index, partial_ask_index,
+        &bid_orders, &ask_orders, &bid_market_orders, &ask_market_orders, average_price_bids, average_price_asks,
+        solver_orders, balances, &address_list_bid, &address_list_ask, &address_list_bid_market, &address_list_ask_market,
+        &amount_0_final_bid, &amount_1_final_bid, &amount_0_final_ask, &amount_1_final_ask,
+        &amount_0_final_bid_market, &amount_1_final_bid_market, &amount_0_final_ask_market, &amount_1_final_ask_market,
+        first_bid_order, first_ask_order, &final_bid_order, &final_ask_order,
+    )
+}
+
+/// [`produce_solution_settle`] for a contract of any batch size. Every order list must be
+/// exactly `variant.batch` long and every address and balance list one longer, the last
+/// entry being the solver.
+#[allow(clippy::too_many_arguments)]
+pub fn produce_solution_settle_batch(
+    variant: &ContractVariant,
+    partial_amount_bid: i64,
+    partial_amount_ask: i64,
+    partial_bid_index: i64,
+    partial_ask_index: i64,
+    bid_orders: &[SettleOrder],
+    ask_orders: &[SettleOrder],
+    solver_orders: [LimitOrder; 2],
+    balances: &Balances,
+    address_list_bid: &[[Word; 4]],
+    address_list_ask: &[[Word; 4]],
+    amount_0_final_bid: &[i64],
+    amount_1_final_bid: &[i64],
+    amount_0_final_ask: &[i64],
+    amount_1_final_ask: &[i64],
+    first_bid_order: i64,
+    first_ask_order: i64,
+    final_bid_order: &[LimitOrder],
+    final_ask_order: &[LimitOrder],
+) -> Result<Solution> {
+    let n = variant.batch;
+    check_len("bid_orders", bid_orders.len(), n)?;
+    check_len("ask_orders", ask_orders.len(), n)?;
+    check_len("final_bid_order", final_bid_order.len(), n)?;
+    check_len("final_ask_order", final_ask_order.len(), n)?;
+    for (name, len) in [
+        ("address_list_bid", address_list_bid.len()),
+        ("address_list_ask", address_list_ask.len()),
+        ("amount_0_final_bid", amount_0_final_bid.len()),
+        ("amount_1_final_bid", amount_1_final_bid.len()),
+        ("amount_0_final_ask", amount_0_final_ask.len()),
+        ("amount_1_final_ask", amount_1_final_ask.len()),
+    ] {
+        check_len(name, len, n + 1)?;
+    }
+
+    // same layout as settle::Vars, with the arrays sized by the variant
+    let predicate_data: Vec<Value> = vec![
+        vec![partial_amount_bid],
+        vec![partial_amount_ask],
+        vec![partial_bid_index],
+        vec![partial_ask_index],
+        settle_orders_value(bid_orders),
+        settle_orders_value(ask_orders),
+        limit_orders_value(&solver_orders),
+    ];
+
+    let mut mutations = balance_mutations(storage::mutations(), balances, [
+        (address_list_bid, amount_0_final_bid, amount_1_final_bid),
+        (address_list_ask, amount_0_final_ask, amount_1_final_ask),
+    ], &[])?;
+    mutations = mutations.first_bid_order(first_bid_order);
+    mutations = mutations.first_ask_order(first_ask_order);
+    mutations = final_order_mutations(mutations, bid_orders, final_bid_order, ask_orders, final_ask_order);
+    let mut state_mutations: Vec<Mutation> = mutations.into();
+    clear_filled_orders(&mut state_mutations, [(bid_orders, final_bid_order, true), (ask_orders, final_ask_order, false)])?;
+
+    Ok(Solution {
+        predicate_to_solve: variant.predicate("settle")?,
+        predicate_data,
+        state_mutations,
+    })
+}
+
+/// [`produce_solution_market_order`] for a contract of any batch size. Order and market
+/// lists must be exactly `variant.batch` long, the limit order address and balance lists
+/// one longer.
+#[allow(clippy::too_many_arguments)]
+pub fn produce_solution_market_order_batch(
+    variant: &ContractVariant,
+    partial_amount_bid: i64,
+    partial_amount_ask: i64,
+    partial_bid_index: i64,
+    partial_ask_index: i64,
+    bid_orders: &[SettleOrder],
+    ask_orders: &[SettleOrder],
+    bid_market_orders: &[MarketOrder],
+    ask_market_orders: &[MarketOrder],
+    average_price_bids: i64,
+    average_price_asks: i64,
+    solver_orders: [LimitOrder; 2],
+    balances: &Balances,
+    address_list_bid: &[[Word; 4]],
+    address_list_ask: &[[Word; 4]],
+    address_list_bid_market: &[[Word; 4]],
+    address_list_ask_market: &[[Word; 4]],
+    amount_0_final_bid: &[i64],
+    amount_1_final_bid: &[i64],
+    amount_0_final_ask: &[i64],
+    amount_1_final_ask: &[i64],
+    amount_0_final_bid_market: &[i64],
+    amount_1_final_bid_market: &[i64],
+    amount_0_final_ask_market: &[i64],
+    amount_1_final_ask_market: &[i64],
+    first_bid_order: i64,
+    first_ask_order: i64,
+    final_bid_order: &[LimitOrder],
+    final_ask_order: &[LimitOrder],
+) -> Result<Solution> {
+    let n = variant.batch;
+    for (name, len) in [
+        ("bid_orders", bid_orders.len()),
+        ("ask_orders", ask_orders.len()),
+        ("bid_market_orders", bid_market_orders.len()),
+        ("ask_market_orders", ask_market_orders.len()),
+        ("address_list_bid_market", address_list_bid_market.len()),
+        ("address_list_ask_market", address_list_ask_market.len()),
+        ("amount_0_final_bid_market", amount_0_final_bid_market.len()),
+        ("amount_1_final_bid_market", amount_1_final_bid_market.len()),
+        ("amount_0_final_ask_market", amount_0_final_ask_market.len()),
+        ("amount_1_final_ask_market", amount_1_final_ask_market.len()),
+        ("final_bid_order", final_bid_order.len()),
+        ("final_ask_order", final_ask_order.len()),
+    ] {
+        check_len(name, len, n)?;
+    }
+    for (name, len) in [
+        ("address_list_bid", address_list_bid.len()),
+        ("address_list_ask", address_list_ask.len()),
+        ("amount_0_final_bid", amount_0_final_bid.len()),
+        ("amount_1_final_bid", amount_1_final_bid.len()),
+        ("amount_0_final_ask", amount_0_final_ask.len()),
+        ("amount_1_final_ask", amount_1_final_ask.len()),
+    ] {
+        check_len(name, len, n + 1)?;
+    }
+
+    // same layout as settleMarketOrders::Vars, with the arrays sized by the variant
+    let predicate_data: Vec<Value> = vec![
+        vec![partial_amount_bid],
+        vec![partial_amount_ask],
+        vec![partial_bid_index],
+        vec![partial_ask_index],
+        market_orders_value(bid_market_orders),
+        market_orders_value(ask_market_orders),
+        settle_orders_value(bid_orders),
+        settle_orders_value(ask_orders),
+        vec![average_price_bids],
+        vec![average_price_asks],
+        limit_orders_value(&solver_orders),
+    ];
+
+    let mut mutations = balance_mutations(storage::mutations(), balances, [
+        (address_list_bid, amount_0_final_bid, amount_1_final_bid),
+        (address_list_ask, amount_0_final_ask, amount_1_final_ask),
+    ], &[
+        (address_list_bid_market, amount_0_final_bid_market, amount_1_final_bid_market),
+        (address_list_ask_market, amount_0_final_ask_market, amount_1_final_ask_market),
+    ])?;
+    mutations = mutations.first_bid_order(first_bid_order);
+    mutations = mutations.first_ask_order(first_ask_order);
+    mutations = final_order_mutations(mutations, bid_orders, final_bid_order, ask_orders, final_ask_order);
+    let mut state_mutations: Vec<Mutation> = mutations.into();
+    clear_filled_orders(&mut state_mutations, [(bid_orders, final_bid_order, true), (ask_orders, final_ask_order, false)])?;
+
+    Ok(Solution {
+        predicate_to_solve: variant.predicate("settleMarketOrders")?,
+        predicate_data,
+        state_mutations,
+    })
+}
+
+fn check_len(name: &str, len: usize, expected: usize) -> Result<()> {
+    if len != expected {
+        return Err(SolverError::Validation(format!("{} has {} entries, the contract takes {}", name, len, expected)));
+    }
+    Ok(())
+}
+
+// One list of addresses with the balances each slot leaves behind.
+type BalanceSlots<'a> = (&'a [[Word; 4]], &'a [i64], &'a [i64]);
+
+// Writes every address's balances once. The predicate checks each slot's balances against
+// its own fill, so a trader with two slots would need two different values under one key:
+// any address but the zero address of the padding slots may only appear once. The last
+// slot of both limit order lists is the solver, whose balances are one result rather than
+// two changes, so both have to agree and it is counted once. Padding slots are written
+// too, unchanged, because the predicate reads the zero address's balances for them.
+fn balance_mutations(
+    mut mutations: storage::Mutations,
+    balances: &Balances,
+    [bid, ask]: [BalanceSlots; 2],
+    market: &[BalanceSlots],
+) -> Result<storage::Mutations> {
+    let last = |(addrs, amounts_0, amounts_1): BalanceSlots| (addrs.last().copied(), amounts_0.last().copied(), amounts_1.last().copied());
+    if last(bid) != last(ask) {
+        return Err(SolverError::Validation("the solver slots of the bid and ask lists differ".to_string()));
+    }
+    let (addrs, amounts_0, amounts_1) = ask;
+    let n = addrs.len().saturating_sub(1);
+    let ask = (&addrs[..n], &amounts_0[..n], &amounts_1[..n]);
+
+    let mut finals: Vec<([Word; 4], i64, i64)> = vec![];
+    for (addrs, amounts_0, amounts_1) in [bid, ask].into_iter().chain(market.iter().copied()) {
+        for ((&addr, &amount_0), &amount_1) in addrs.iter().zip(amounts_0).zip(amounts_1) {
+            let seen = finals.iter().any(|(seen, _, _)| *seen == addr);
+            if addr == [0; 4] {
+                if !seen {
+                    finals.push((addr, balances.get_0(&addr), balances.get_1(&addr)));
+                }
+            } else if seen {
+                return Err(SolverError::Validation(format!("{:?} has more than one slot in the settlement", addr)));
+            } else {
+                finals.push((addr, amount_0, amount_1));
+            }
+        }
+    }
+    for (addr, amount_0, amount_1) in finals {
+        mutations = mutations.balances_0(|map| map.entry(addr, amount_0));
+        mutations = mutations.balances_1(|map| map.entry(addr, amount_1));
+    }
+    Ok(mutations)
+}
+
+fn final_order_mutations(
+    mut mutations: storage::Mutations,
+    bid_orders: &[SettleOrder],
+    final_bid_order: &[LimitOrder],
+    ask_orders: &[SettleOrder],
+    final_ask_order: &[LimitOrder],
+) -> storage::Mutations {
+    for (order, last) in bid_orders.iter().zip(final_bid_order) {
+        mutations = mutations.bid_orders(|map| map.entry(order.index, |tup|
+            tup.max_amnt(last.max_amnt)
+            .price(last.price)
+            .isBid(last.is_bid)
+            .addr(last.addr)
+            .auth(last.auth)
+            .next_key(last.next_key)
+        ));
+    }
+    for (order, last) in ask_orders.iter().zip(final_ask_order) {
+        mutations = mutations.ask_orders(|map| map.entry(order.index, |tup|
+            tup.max_amnt(last.max_amnt)
+            .price(last.price)
+            .isBid(last.is_bid)
+            .addr(last.addr)
+            .auth(last.auth)
+            .next_key(last.next_key)
+        ));
+    }
+    mutations
+}
+
+// Deletes the slots of orders `final_*_order` leaves empty instead of writing zeros, so an
+// add can take the index again: it only accepts a nil slot, and the settle predicates read
+// nil fields as 0. The index 0 padding slots keep their zeros.
+fn clear_filled_orders(mutations: &mut [Mutation], sides: [(&[SettleOrder], &[LimitOrder], bool); 2]) -> Result<()> {
+    for (orders, finals, is_bid) in sides {
+        for (order, last) in orders.iter().zip(finals) {
+            if order.index == 0 || last.max_amnt != 0 {
+                continue;
+            }
+            let keys = order_keys(order.index, is_bid)?;
+            for mutation in mutations.iter_mut().filter(|m| keys.contains(&m.key)) {
+                mutation.value.clear();
+            }
+        }
+    }
+    Ok(())
+}
+
+// Tuples are laid out field after field, arrays element after element.
+fn settle_orders_value(orders: &[SettleOrder]) -> Value {
+    orders.iter().flat_map(|o| std::iter::once(o.index).chain(o.auth)).collect()
+}
+
+fn market_orders_value(orders: &[MarketOrder]) -> Value {
+    orders.iter().flat_map(|o| std::iter::once(o.amount).chain(o.addr).chain(o.auth)).collect()
+}
+
+fn limit_orders_value(orders: &[LimitOrder]) -> Value {
+    orders
+        .iter()
+        .flat_map(|o| [o.max_amnt, o.price, o.is_bid as Word].into_iter().chain(o.addr).chain(o.auth).chain([o.next_key]))
+        .collect()
+}
+
@@ -0,0 +1,104 @@
+//! Typed reads of the orderbook contract's storage.
+use crate::error::{Result, SolverError};
+use crate::keys::{balances_0_key, balances_1_key, first_order_key, order_count_key, order_keys};
+use crate::node;
+use crate::solutions::LimitOrder;
+use essential_app_utils::db::Dbs;
+use essential_types::{ContentAddress, Key, Word};
+
+/// Reads contract storage at the head of the chain and decodes it into the crate's types.
+///
+/// Missing values read as 0, just like they do inside the contract.
+pub struct StateReader<'a> {
+    dbs: &'a Dbs,
+    contract: ContentAddress,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(dbs: &'a Dbs, contract: ContentAddress) -> Self {
+        Self { dbs, contract }
+    }
+
+    /// `balances_0[addr]`.
+    pub async fn balance0(&self, addr: [Word; 4]) -> Result<i64> {
+        self.int(&balances_0_key(addr)?).await
+    }
+
+    /// `balances_1[addr]`.
+    pub async fn balance1(&self, addr: [Word; 4]) -> Result<i64> {
+        self.int(&balances_1_key(addr)?).await
+    }
+
+    /// `bid_orders[index]`, `None` if the slot is empty.
+    pub async fn bid_order(&self, index: i64) -> Result<Option<LimitOrder>> {
+        self.order(index, true).await
+    }
+
+    /// `ask_orders[index]`, `None` if the slot is empty.
+    pub async fn ask_order(&self, index: i64) -> Result<Option<LimitOrder>> {
+        self.order(index, false).await
+    }
+
+    /// `first_bid_order`, 0 if there are no bids.
+    pub async fn first_bid(&self) -> Result<i64> {
+        self.int(&first_order_key(true)?).await
+    }
+
+    /// `first_ask_order`, 0 if there are no asks.
+    pub async fn first_ask(&self) -> Result<i64> {
+        self.int(&first_order_key(false)?).await
+    }
+
+    /// `(number_of_bid_orders, number_of_ask_orders)`.
+    ///
+    /// No predicate of the current contract updates these counters, so they stay 0. Walk
+    /// the lists with `loader::load_side` to count the resting orders.
+    pub async fn order_counts(&self) -> Result<(i64, i64)> {
+        Ok((self.int(&order_count_key(true)?).await?, self.int(&order_count_key(false)?).await?))
+    }
+
+    /// `bid_orders[index]` or `ask_orders[index]`, `None` if the slot is empty.
+    pub async fn order(&self, index: i64, is_bid: bool) -> Result<Option<LimitOrder>> {
+        let [max_amnt, price, side, addr, auth, next_key] = order_keys(index, is_bid)?;
+        let max_amnt = self.int(&max_amnt).await?;
+        if max_amnt == 0 {
+            return Ok(None);
+        }
+        Ok(Some(LimitOrder {
+            max_amnt,
+            price: self.int(&price).await?,
+            is_bid: self.int(&side).await? != 0,
+            addr: self.b256(&addr).await?,
+            auth: self.b256(&auth).await?,
+            next_key: self.int(&next_key).await?,
+        }))
+    }
+
+    /// Whether every field of `bid_orders[index]` or `ask_orders[index]` is nil, which an add
+    /// requires of the slot it writes. A slot zeroed by a remove reads as empty through
+    /// [`Self::order`] but is not vacant.
+    pub async fn is_vacant(&self, index: i64, is_bid: bool) -> Result<bool> {
+        for key in order_keys(index, is_bid)? {
+            if !node::query(self.dbs, &self.contract, &key).await?.unwrap_or_default().is_empty() {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    async fn int(&self, key: &Key) -> Result<Word> {
+        match node::query(self.dbs, &self.contract, key).await?.as_deref() {
+            None | Some([]) => Ok(0),
+            Some([word]) => Ok(*word),
+            Some(words) => Err(SolverError::Decode(format!("expected 1 word at {:?}, got {:?}", key, words))),
+        }
+    }
+
+    async fn b256(&self, key: &Key) -> Result<[Word; 4]> {
+        match node::query(self.dbs, &self.contract, key).await?.as_deref() {
+            None | Some([]) => Ok([0; 4]),
+            Some(&[a, b, c, d]) => Ok([a, b, c, d]),
+            Some(words) => Err(SolverError::Decode(format!("expected 4 words at {:?}, got {:?}", key, words))),
+        }
+    }
+}
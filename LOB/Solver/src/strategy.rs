@@ -0,0 +1,242 @@
+//! How the solver prices its own pair of orders in a `settle` solution.
+//!
+//! The solver is the counterparty of every settled order: its bid (`solver_orders[0]`)
+//! buys everything the settled asks sell and its ask (`solver_orders[1]`) sells everything
+//! the settled bids buy. The contract only requires that the solver takes out no more of
+//! either token than the orders put in,
+//!
+//! ```text
+//! ask_max * bid_price - bid_max * ask_price >= ask_token0 - bid_token0
+//! ```
+//!
+//! and that the solver's balances stay non-negative, so the prices are free for a
+//! [`SolverStrategy`] to choose within those bounds.
+use crate::error::{Result, SolverError};
+use crate::fixed::{add, notional, sub};
+use crate::settlement::Fill;
+use crate::solutions::LimitOrder;
+use essential_types::Word;
+
+/// Chooses the two solver orders passed to `produce_solution_settle`.
+pub trait SolverStrategy {
+    /// Returns the solver's bid, buying every lot `asks` sell, and its ask, selling every
+    /// lot `bids` buy. `balance_0`/`balance_1` are the solver's balances before settling.
+    fn solver_orders(
+        &self,
+        solver_addr: [Word; 4],
+        bids: &[Fill],
+        asks: &[Fill],
+        balance_0: i64,
+        balance_1: i64,
+    ) -> Result<[LimitOrder; 2]>;
+}
+
+/// Token1 and token0 exchanged by one side's fills.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Totals {
+    pub amount: i64,
+    pub token0: i64,
+}
+
+impl Totals {
+    pub fn of(fills: &[Fill]) -> Result<Totals> {
+        fills.iter().try_fold(Totals::default(), |totals, fill| {
+            Ok(Totals {
+                amount: add(totals.amount, fill.amount)?,
+                token0: add(totals.token0, notional(fill.amount, fill.order.price)?)?,
+            })
+        })
+    }
+
+    /// Average price rounded down, 0 for an empty side.
+    pub fn floor_price(&self) -> i64 {
+        if self.amount == 0 { 0 } else { self.token0.div_euclid(self.amount) }
+    }
+
+    /// Average price rounded up, 0 for an empty side.
+    pub fn ceil_price(&self) -> i64 {
+        if self.amount == 0 { 0 } else { ceil_div(self.token0, self.amount) }
+    }
+}
+
+/// Buys the asks at their average price plus one and sells to the bids at their average
+/// price rounded down, so the solver never loses token0. This is what `plan_settle` uses.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Vwap;
+
+impl SolverStrategy for Vwap {
+    fn solver_orders(&self, solver_addr: [Word; 4], bids: &[Fill], asks: &[Fill], balance_0: i64, balance_1: i64) -> Result<[LimitOrder; 2]> {
+        let (bid, ask) = (Totals::of(bids)?, Totals::of(asks)?);
+        let buy = if ask.amount != 0 { ask.floor_price() + 1 } else { 0 };
+        conserve(solver_addr, bid, ask, buy, bid.floor_price(), balance_0, balance_1)
+    }
+}
+
+/// Quotes both orders at the midpoint of the two average prices. The ask is lowered if
+/// that would take more token0 than the orders leave.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Mid;
+
+impl SolverStrategy for Mid {
+    fn solver_orders(&self, solver_addr: [Word; 4], bids: &[Fill], asks: &[Fill], balance_0: i64, balance_1: i64) -> Result<[LimitOrder; 2]> {
+        let (bid, ask) = (Totals::of(bids)?, Totals::of(asks)?);
+        let mid = match (bid.amount, ask.amount) {
+            (0, _) => ask.ceil_price(),
+            (_, 0) => bid.floor_price(),
+            _ => add(bid.floor_price(), ask.ceil_price())? / 2,
+        };
+        conserve(solver_addr, bid, ask, mid, mid, balance_0, balance_1)
+    }
+}
+
+/// Buys `spread / 2` below and sells `spread - spread / 2` above the midpoint of the two
+/// average prices, narrowing the spread where conservation requires it.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedSpread {
+    /// In on-chain price units.
+    pub spread: i64,
+}
+
+impl SolverStrategy for FixedSpread {
+    fn solver_orders(&self, solver_addr: [Word; 4], bids: &[Fill], asks: &[Fill], balance_0: i64, balance_1: i64) -> Result<[LimitOrder; 2]> {
+        let (bid, ask) = (Totals::of(bids)?, Totals::of(asks)?);
+        let mid = match (bid.amount, ask.amount) {
+            (0, _) => ask.ceil_price(),
+            (_, 0) => bid.floor_price(),
+            _ => add(bid.floor_price(), ask.ceil_price())? / 2,
+        };
+        let buy = sub(mid, self.spread / 2)?.max(0);
+        let sell = add(mid, self.spread - self.spread / 2)?;
+        conserve(solver_addr, bid, ask, buy, sell, balance_0, balance_1)
+    }
+}
+
+/// Takes as much token0 as the conservation constraint allows. The bid stays at the asks'
+/// average price rounded up, or is raised by the few ticks that make the integer rounding
+/// of the ask price lose nothing.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProfitMax;
+
+/// How many bid prices [`ProfitMax`] tries when looking for the one that rounds best.
+const PROFIT_MAX_SEARCH: i64 = 1024;
+
+impl SolverStrategy for ProfitMax {
+    fn solver_orders(&self, solver_addr: [Word; 4], bids: &[Fill], asks: &[Fill], balance_0: i64, balance_1: i64) -> Result<[LimitOrder; 2]> {
+        let (bid, ask) = (Totals::of(bids)?, Totals::of(asks)?);
+        let limit = sub(bid.token0, ask.token0)?;
+        let mut buy = ask.ceil_price();
+        if bid.amount != 0 && ask.amount != 0 {
+            // the ask price is floor((limit + ask * buy) / bid), pick the buy price that
+            // leaves the smallest remainder
+            let mut best = (i64::MAX, buy);
+            for candidate in buy..add(buy, PROFIT_MAX_SEARCH.min(bid.amount))? {
+                let remainder = add(limit, notional(ask.amount, candidate)?)?.rem_euclid(bid.amount);
+                if remainder < best.0 {
+                    best = (remainder, candidate);
+                }
+                if remainder == 0 {
+                    break;
+                }
+            }
+            buy = best.1;
+        }
+        // the largest ask price conservation allows at that bid price
+        let sell = if bid.amount != 0 { add(limit, notional(ask.amount, buy)?)?.div_euclid(bid.amount) } else { 0 };
+        conserve(solver_addr, bid, ask, buy, sell, balance_0, balance_1)
+    }
+}
+
+// Lowers the solver's ask price, or raises its bid price when it sells nothing, until the
+// solver takes no more token0 than the orders leave, then checks its final balances.
+fn conserve(
+    solver_addr: [Word; 4],
+    bid: Totals,
+    ask: Totals,
+    mut buy: i64,
+    mut sell: i64,
+    balance_0: i64,
+    balance_1: i64,
+) -> Result<[LimitOrder; 2]> {
+    let limit = sub(bid.token0, ask.token0)?;
+    let gain = sub(notional(bid.amount, sell)?, notional(ask.amount, buy)?)?;
+    if gain > limit {
+        if bid.amount != 0 {
+            sell = add(limit, notional(ask.amount, buy)?)?.div_euclid(bid.amount);
+        } else {
+            buy = ceil_div(sub(notional(bid.amount, sell)?, limit)?, ask.amount);
+        }
+    }
+    if buy < 0 || sell < 0 {
+        return Err(SolverError::Validation(format!(
+            "no non-negative solver prices conserve token0 ({} bid, {} ask)",
+            buy, sell
+        )));
+    }
+
+    let final_0 = add(sub(balance_0, notional(ask.amount, buy)?)?, notional(bid.amount, sell)?)?;
+    let final_1 = sub(add(balance_1, ask.amount)?, bid.amount)?;
+    if final_0 < 0 || final_1 < 0 {
+        return Err(SolverError::Validation(format!(
+            "solver balances would end at ({}, {})",
+            final_0, final_1
+        )));
+    }
+
+    Ok([
+        LimitOrder { max_amnt: ask.amount, price: buy, is_bid: true, addr: solver_addr, auth: solver_addr, next_key: 0 },
+        LimitOrder { max_amnt: bid.amount, price: sell, is_bid: false, addr: solver_addr, auth: solver_addr, next_key: 0 },
+    ])
+}
+
+fn ceil_div(a: i64, b: i64) -> i64 {
+    a.div_euclid(b) + i64::from(a.rem_euclid(b) != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::Order;
+
+    fn fill(index: i64, price: i64, is_bid: bool, amount: i64) -> Fill {
+        let addr = [index; 4];
+        Fill { order: Order { index, max_amnt: amount, price, is_bid, addr, auth: addr }, amount }
+    }
+
+    // what the solver takes out of the orders' token0, which the contract caps at 0
+    fn excess(orders: &[LimitOrder; 2], bids: &[Fill], asks: &[Fill]) -> i64 {
+        let (bid, ask) = (Totals::of(bids).unwrap(), Totals::of(asks).unwrap());
+        -bid.token0 + ask.token0 - orders[0].max_amnt * orders[0].price + orders[1].max_amnt * orders[1].price
+    }
+
+    #[test]
+    fn every_strategy_conserves_token0() {
+        let bids = [fill(1, 107, true, 4), fill(2, 105, true, 3)];
+        let asks = [fill(3, 100, false, 5), fill(4, 103, false, 2)];
+        let strategies: [&dyn SolverStrategy; 4] = [&Vwap, &Mid, &FixedSpread { spread: 50 }, &ProfitMax];
+        for strategy in strategies {
+            let orders = strategy.solver_orders([9; 4], &bids, &asks, 10_000, 100).unwrap();
+            assert_eq!((orders[0].max_amnt, orders[1].max_amnt), (7, 7));
+            assert!(excess(&orders, &bids, &asks) <= 0, "{:?}", orders);
+        }
+    }
+
+    #[test]
+    fn profit_max_takes_the_whole_surplus() {
+        // 7 lots bought for 743 and 5 sold for 500, a bid price of 104 makes the ask
+        // price 109 divide the surplus exactly
+        let bids = [fill(1, 107, true, 4), fill(2, 105, true, 3)];
+        let asks = [fill(3, 100, false, 5)];
+        let vwap = Vwap.solver_orders([9; 4], &bids, &asks, 10_000, 100).unwrap();
+        let max = ProfitMax.solver_orders([9; 4], &bids, &asks, 10_000, 100).unwrap();
+        assert_eq!((max[0].price, max[1].price), (104, 109));
+        assert_eq!(excess(&max, &bids, &asks), 0);
+        assert!(excess(&max, &bids, &asks) > excess(&vwap, &bids, &asks));
+    }
+
+    #[test]
+    fn rejects_an_underfunded_solver() {
+        let bids = [fill(1, 105, true, 10)];
+        let result = Vwap.solver_orders([9; 4], &bids, &[], 0, 5);
+        assert!(matches!(result, Err(SolverError::Validation(_))));
+    }
+}
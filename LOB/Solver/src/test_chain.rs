@@ -0,0 +1,148 @@
+//! Test fixture: the orderbook contract deployed into a fresh local node.
+//!
+//! Scenario tests start from [`TestChain::deploy`] and push every solution set through
+//! [`TestChain::submit_and_build`], so they all compile, deploy and build blocks the same
+//! way.
+use crate::batch::{DEFAULT_BATCH, pack};
+use crate::diagnostics::explain;
+use crate::node;
+use crate::settlement::SETTLE_BATCH;
+use crate::solutions::INSERT_RUN;
+use crate::variant::{self, ContractVariant};
+use crate::state::StateReader;
+use essential_app_utils as utils;
+use essential_app_utils::compile::compile_pint_project;
+use essential_app_utils::db::Dbs;
+use essential_node::db;
+use essential_node_types::{BigBang, Block, BlockHeader};
+use essential_types::{ContentAddress, PredicateAddress, Program, contract::Contract, solution::{Mutation, Solution, SolutionSet}};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const ORDERBOOK: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../PintLOB/orderbook");
+
+/// A local builder and node with the orderbook contract registered.
+pub struct TestChain {
+    pub dbs: Dbs,
+    pub address: ContentAddress,
+    /// Predicate names and ABI of the deployed contract.
+    pub variant: ContractVariant,
+}
+
+/// What building one block did with a submitted solution set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockOutcome {
+    /// Number of solution sets the builder rejected.
+    pub failed: usize,
+    /// The rejected sets explained by predicate, intent and decoded data, empty if none were.
+    pub report: String,
+}
+
+impl BlockOutcome {
+    pub fn is_ok(&self) -> bool {
+        self.failed == 0
+    }
+
+    #[track_caller]
+    pub fn assert_ok(&self) {
+        assert!(self.is_ok(), "{}", self.report);
+    }
+
+    #[track_caller]
+    pub fn assert_failed(&self) {
+        assert!(!self.is_ok(), "the block was built without failures");
+    }
+}
+
+impl TestChain {
+    /// Compiles `../PintLOB/orderbook` and deploys it.
+    pub async fn deploy() -> Self {
+        Self::deploy_project(ORDERBOOK.into(), SETTLE_BATCH).await
+    }
+
+    /// Compiles a copy of `../PintLOB/orderbook` settling `batch` orders per side and
+    /// deploys it.
+    pub async fn deploy_batch(batch: usize) -> Self {
+        Self::deploy_widths(batch, INSERT_RUN).await
+    }
+
+    /// Compiles a copy of `../PintLOB/orderbook` settling `batch` orders per side and
+    /// linking in runs of up to `insert_run` new orders, and deploys it.
+    pub async fn deploy_widths(batch: usize, insert_run: usize) -> Self {
+        Self::deploy_project(variant::write_project(Path::new(ORDERBOOK), batch, insert_run).unwrap(), batch).await
+    }
+
+    /// Compiles the pint project at `path`, whose settlement takes `batch` orders per side,
+    /// and deploys it.
+    pub async fn deploy_project(path: PathBuf, batch: usize) -> Self {
+        // several tests share the process, only the first one installs the subscriber
+        let _ = tracing_subscriber::fmt::try_init();
+        let (contract, programs): (Contract, Vec<Program>) = compile_pint_project(path.clone()).await.unwrap();
+        let address = essential_hash::contract_addr::from_contract(&contract);
+        let variant = ContractVariant::from_project(&path, &contract, batch).unwrap();
+
+        let dbs = utils::db::new_dbs().await;
+        let big_bang = BigBang::default();
+        utils::deploy::register_contract_and_programs(
+            &dbs.builder,
+            &big_bang.contract_registry,
+            &big_bang.program_registry,
+            &contract,
+            programs,
+        )
+        .await
+        .unwrap();
+        TestChain { dbs, address, variant }
+    }
+
+    /// Submits `solution_set`, validates it against the current state and builds a block.
+    /// Only infrastructure errors panic; rejected solutions end up in the outcome.
+    pub async fn submit_and_build(&self, solution_set: SolutionSet) -> BlockOutcome {
+        node::submit(&self.dbs, solution_set.clone()).await.unwrap();
+        node::validate(&self.dbs, solution_set.clone()).await.unwrap();
+        let o = utils::builder::build_default(&self.dbs).await.unwrap();
+        let report = if o.failed.is_empty() { String::new() } else { explain(&o.failed, &[&solution_set], &self.variant) };
+        BlockOutcome { failed: o.failed.len(), report }
+    }
+
+    /// Packs `solutions` into solution sets without key conflicts and builds one block per
+    /// set, in order. Returns the first failed outcome, or the last one.
+    pub async fn submit_batched(&self, solutions: Vec<Solution>) -> BlockOutcome {
+        let mut outcome = BlockOutcome { failed: 0, report: String::new() };
+        for solution_set in pack(solutions, DEFAULT_BATCH).unwrap() {
+            outcome = self.submit_and_build(solution_set).await;
+            if !outcome.is_ok() {
+                break;
+            }
+        }
+        outcome
+    }
+
+    /// Writes `state_mutations` into the contract's storage in a block of their own that no
+    /// predicate checks, to set up states the contract never lets a solution leave. The
+    /// builder does not know about that block, so build nothing afterwards.
+    pub async fn write_unchecked(&self, state_mutations: Vec<Mutation>) {
+        let predicate_to_solve = PredicateAddress { contract: self.address.clone(), predicate: ContentAddress([0; 32]) };
+        let solutions = vec![Solution { predicate_to_solve, predicate_data: vec![], state_mutations }];
+        self.dbs
+            .node
+            .acquire_then(move |conn| {
+                db::with_tx(conn, |tx| {
+                    let number = match db::get_latest_finalized_block_address(tx)? {
+                        Some(head) => db::get_block_header(tx, &head)?.map_or(0, |header| header.number),
+                        None => 0,
+                    };
+                    let header = BlockHeader { number: number + 1, timestamp: Duration::default() };
+                    let block = db::insert_block(tx, &Block { header, solution_sets: vec![SolutionSet { solutions }] })?;
+                    db::finalize_block(tx, &block)
+                })
+            })
+            .await
+            .unwrap();
+    }
+
+    /// Reads the contract's storage at the head of the chain.
+    pub fn state(&self) -> StateReader<'_> {
+        StateReader::new(&self.dbs, self.address.clone())
+    }
+}
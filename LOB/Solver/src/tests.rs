@@ -0,0 +1,1063 @@
+use crate::book::{Balances, Order, OrderBook};
+use crate::error::SolverError;
+use crate::ids::{IdScheme, OrderIdAllocator};
+use crate::keys::{balances_0_key, balances_1_key, first_order_key, generate_random_hash, hex_to_i64_array, hex_to_word_array, order_keys};
+use crate::loader::{load_orderbook, load_side};
+use crate::matching::{match_book, sweep};
+use crate::reconcile::reconcile;
+use crate::replay::Replay;
+use crate::settlement::{plan_settle, plan_settle_with};
+use crate::solutions::*;
+use crate::strategy::Vwap;
+use crate::test_chain::TestChain;
+use crate::trace::parse_orderbook_file;
+use essential_types::{Key, Word, solution::{Mutation, SolutionSet}};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use std::collections::{BTreeMap, HashSet};
+
+ //Next, let's start the essential-builder
+//  #[tokio::test]
+#[tokio::test(flavor = "multi_thread")]
+ async fn test_add_limit_order() {
+        // Convert the addresses for our order
+    let _addr_zero_i64 = hex_to_i64_array("0x0000000000000000000000000000000000000000000000000000000000000000").unwrap();
+     let _addr0_i64 = hex_to_i64_array("0x5B5F934E382FDC4AD1C4AB2448B32BD66B5C53D5A3D5166A9EF48CB6DB3B2B95").unwrap();
+     let _addr1_i64 = hex_to_i64_array("0x7AE73AE363588924F50D5B87F807642B7193D2A0265B451000FAE4318007CD86").unwrap();
+     let _addr2_i64 = hex_to_i64_array("0x5F9C2BD1A47E8039D1A3B687DCE92F33A187E904B61D2A3C9F82C0EF99B72D41").unwrap();
+     let _auth_i64 = hex_to_i64_array("0x7AE73AE363588924F50D5B87F807642B7193D2A0265B451000FAE4318007CD86").unwrap();
+     let _addr_word = hex_to_word_array("0x5B5F934E382FDC4AD1C4AB2448B32BD66B5C53D5A3D5166A9EF48CB6DB3B2B95").unwrap();
+     let _auth_word = hex_to_word_array("0x7AE73AE363588924F50D5B87F807642B7193D2A0265B451000FAE4318007CD86").unwrap();
+
+    let chain = TestChain::deploy().await;
+
+    // Next, let's submit a solution
+    let solution0 = produce_solution_deposit(
+        10000,         //amount_0_delta: i64,
+        10000,         //amount_0_final: i64,
+        100,         //amount_1_delta: i64,
+        100,         //amount_1_final: i64,
+        _addr0_i64,   //addr_word: [Word; 4],
+        _addr0_i64,   //key_word: [Word; 4],
+        _addr0_i64    //auth_word: [Word; 4]
+        );
+
+    let solution1 = produce_solution_deposit(
+        10000,         //amount_0_delta: i64,
+        10000,         //amount_0_final: i64,
+        100,         //amount_1_delta: i64,
+        100,         //amount_1_final: i64,
+        _addr1_i64,   //addr_word: [Word; 4],
+        _addr1_i64,   //key_word: [Word; 4],
+        _addr1_i64    //auth_word: [Word; 4]
+    );
+
+    let solution_set = SolutionSet {
+        solutions: vec![solution0, solution1],
+    };
+    // println!("solution_set: {:?}", solution_set);
+
+    chain.submit_and_build(solution_set).await.assert_ok();
+
+    let solution_add_bid = produce_solution_add_limit_order_bid(
+        0, // leading_key
+        0, // trailing_key
+        LimitOrder {
+            max_amnt: 100,
+            price: 100,
+            is_bid: true,
+            addr: _addr0_i64,
+            auth: _auth_i64,
+            next_key: 0,
+        },
+        1, // new_index
+        0, // leading_order_next
+        1, // first_order_index
+    );
+
+    let _solution_add_bid2 = produce_solution_add_limit_order_bid(
+        0, // leading_key
+        0, // trailing_key
+        LimitOrder {
+            max_amnt: 100,
+            price: 100,
+            is_bid: true,
+            addr: _addr0_i64,
+            auth: _auth_i64,
+            next_key: 0,
+        },
+        1, // new_index
+        0, // leading_order_next
+        1, // first_order_index
+    );
+
+    let solution_add_ask = produce_solution_add_limit_order_ask(
+        0, // leading_key
+        0, // trailing_key
+        LimitOrder {
+            max_amnt: 100,
+            price: 100,
+            is_bid: false,
+            addr: _addr1_i64,
+            auth: _auth_i64,
+            next_key: 0,
+        },
+        1, // new_index
+        0, // leading_order_next    
+        1, // first_order_index
+    );
+
+    let solution_set_add = SolutionSet {
+        // solutions: vec![solution_add_bid.clone(), solution_add_ask.clone()],
+        solutions: vec![solution_add_bid.clone()],
+    };
+    // println!("solution_set: {:?}", solution_set);
+
+    chain.submit_and_build(solution_set_add).await.assert_ok();
+
+    let solution_set = SolutionSet {
+        solutions: vec![solution_add_ask.clone()],
+    };
+    // println!("solution_set: {:?}", solution_set);
+
+    chain.submit_and_build(solution_set).await.assert_ok();
+
+    let state = chain.state();
+    let bid_order = state.bid_order(1).await.unwrap();
+     println!("bid_order: {:?}", bid_order);
+
+     let balance_0_addr0 = state.balance0(_addr0_i64).await.unwrap();
+     let balance_1_addr0 = state.balance1(_addr0_i64).await.unwrap();
+     let balance_0_addr1 = state.balance0(_addr1_i64).await.unwrap();
+     let balance_1_addr1 = state.balance1(_addr1_i64).await.unwrap();
+
+     println!("balance_0_addr0: {:?}", balance_0_addr0);
+     println!("balance_1_addr0: {:?}", balance_1_addr0);
+     println!("balance_0_addr1: {:?}", balance_0_addr1);
+     println!("balance_1_addr1: {:?}", balance_1_addr1);
+ }
+
+// Reading both lists back after a mix of front, middle and back adds on each side gives
+// the mirror the adds were planned against, order for order.
+#[tokio::test]
+async fn test_load_orderbook_matches_the_mirror() {
+    let chain = TestChain::deploy().await;
+    let addr = [1, 0, 0, 0];
+    chain.submit_and_build(SolutionSet { solutions: vec![produce_solution_deposit(1_000_000, 1_000_000, 1_000, 1_000, addr, addr, addr)] }).await.assert_ok();
+
+    let mut orderbook = OrderBook::new();
+    let orders = [(11, 100, true), (12, 102, true), (13, 98, true), (14, 100, true), (21, 110, false), (22, 108, false), (23, 112, false), (24, 110, false)];
+    for (index, price, is_bid) in orders {
+        let plan = orderbook.plan_insert(Order { index, max_amnt: 5, price, is_bid, addr, auth: addr }).unwrap();
+        chain.submit_and_build(SolutionSet { solutions: vec![plan.to_solution()] }).await.assert_ok();
+        plan.apply(&mut orderbook);
+    }
+
+    let state = chain.state();
+    let loaded = load_orderbook(&state).await.unwrap();
+    for is_bid in [true, false] {
+        assert_eq!(loaded.chain(is_bid), orderbook.chain(is_bid));
+        let indices: Vec<i64> = loaded.chain(is_bid).iter().map(|o| o.index).collect();
+        assert_eq!(indices, if is_bid { vec![12, 11, 14, 13] } else { vec![22, 21, 24, 23] });
+        // every next_key on chain points at the order the mirror puts next
+        for order in loaded.chain(is_bid) {
+            let on_chain = state.order(order.index, is_bid).await.unwrap().unwrap();
+            assert_eq!(on_chain.next_key, orderbook.next_index(order.index, is_bid));
+        }
+    }
+    assert_eq!((loaded.first_bid_index(), loaded.first_ask_index()), (state.first_bid().await.unwrap(), state.first_ask().await.unwrap()));
+}
+
+// A list that runs in a circle or into an empty slot is reported rather than followed.
+#[tokio::test]
+async fn test_load_orderbook_rejects_broken_lists() {
+    let chain = TestChain::deploy().await;
+    let addr = [1, 0, 0, 0];
+    chain.submit_and_build(SolutionSet { solutions: vec![produce_solution_deposit(1_000_000, 1_000_000, 1_000, 1_000, addr, addr, addr)] }).await.assert_ok();
+    let mut orderbook = OrderBook::new();
+    for (index, price) in [(1, 101), (2, 100)] {
+        let plan = orderbook.plan_insert(Order { index, max_amnt: 5, price, is_bid: true, addr, auth: addr }).unwrap();
+        chain.submit_and_build(SolutionSet { solutions: vec![plan.to_solution()] }).await.assert_ok();
+        plan.apply(&mut orderbook);
+    }
+
+    // the last bid points back at the first, and the ask head at a slot nothing was added to
+    let next_key = order_keys(2, true).unwrap()[5].clone();
+    chain.write_unchecked(vec![
+        Mutation { key: next_key, value: vec![1] },
+        Mutation { key: first_order_key(false).unwrap(), value: vec![7] },
+    ]).await;
+
+    let state = chain.state();
+    let looped = load_side(&state, true).await;
+    assert!(matches!(&looped, Err(SolverError::Decode(e)) if e.contains("loops back to order 1")), "{:?}", looped);
+    let dangling = load_side(&state, false).await;
+    assert!(matches!(&dangling, Err(SolverError::Decode(e)) if e.contains("empty order 7")), "{:?}", dangling);
+    assert!(matches!(load_orderbook(&state).await, Err(SolverError::Decode(_))));
+}
+
+
+
+ #[tokio::test]
+ async fn test_settle_limit_order() {
+        // Convert the addresses for our order
+    let _addr_zero_i64 = hex_to_i64_array("0x0000000000000000000000000000000000000000000000000000000000000000").unwrap();
+     let _addr0_i64 = hex_to_i64_array("0x5B5F934E382FDC4AD1C4AB2448B32BD66B5C53D5A3D5166A9EF48CB6DB3B2B95").unwrap();
+     let _addr1_i64 = hex_to_i64_array("0x7AE73AE363588924F50D5B87F807642B7193D2A0265B451000FAE4318007CD86").unwrap();
+     let _addr2_i64 = hex_to_i64_array("0x5F9C2BD1A47E8039D1A3B687DCE92F33A187E904B61D2A3C9F82C0EF99B72D41").unwrap();
+     let _auth_i64 = hex_to_i64_array("0x7AE73AE363588924F50D5B87F807642B7193D2A0265B451000FAE4318007CD86").unwrap();
+     let _addr_word = hex_to_word_array("0x5B5F934E382FDC4AD1C4AB2448B32BD66B5C53D5A3D5166A9EF48CB6DB3B2B95").unwrap();
+     let _auth_word = hex_to_word_array("0x7AE73AE363588924F50D5B87F807642B7193D2A0265B451000FAE4318007CD86").unwrap();
+
+    let chain = TestChain::deploy().await;
+
+    // Next, let's submit a solution
+    let solution0 = produce_solution_deposit(
+        10000,         //amount_0_delta: i64,
+        10000,         //amount_0_final: i64,
+        100,         //amount_1_delta: i64,
+        100,         //amount_1_final: i64,
+        _addr0_i64,   //addr_word: [Word; 4],
+        _addr0_i64,   //key_word: [Word; 4],
+        _addr0_i64    //auth_word: [Word; 4]
+        );
+
+    let solution1 = produce_solution_deposit(
+        10000,         //amount_0_delta: i64,
+        10000,         //amount_0_final: i64,
+        100,         //amount_1_delta: i64,
+        100,         //amount_1_final: i64,
+        _addr1_i64,   //addr_word: [Word; 4],
+        _addr1_i64,   //key_word: [Word; 4],
+        _addr1_i64    //auth_word: [Word; 4]
+    );
+
+    let solution_set = SolutionSet {
+        solutions: vec![solution0, solution1],
+    };
+    // println!("solution_set: {:?}", solution_set);
+
+    chain.submit_and_build(solution_set).await.assert_ok();
+
+    let solution0 = produce_solution_add_limit_order_bid(
+        0, // leading_key
+        0, // trailing_key
+        LimitOrder {
+            max_amnt: 100,
+            price: 100,
+            is_bid: true,
+            addr: _addr0_i64,
+            auth: _auth_i64,
+            next_key: 0,
+        },
+        1, // new_index
+        0, // leading_order_next
+        1, // first_order_index
+    );
+
+    let solution1 = produce_solution_add_limit_order_ask(
+        0, // leading_key
+        0, // trailing_key
+        LimitOrder {
+            max_amnt: 100,
+            price: 100,
+            is_bid: false,
+            addr: _addr1_i64,
+            auth: _auth_i64,
+            next_key: 0,
+        },
+        1, // new_index
+        0, // leading_order_next    
+        1, // first_order_index
+    );
+
+    let solution_set = SolutionSet {
+        solutions: vec![solution0],
+    };
+    // println!("solution_set: {:?}", solution_set);
+
+    chain.submit_and_build(solution_set).await.assert_ok();
+
+    let solution_set = SolutionSet {
+        solutions: vec![solution1],
+    };
+    // println!("solution_set: {:?}", solution_set);
+
+    chain.submit_and_build(solution_set).await.assert_ok();
+
+    // Initialize the bid_orders array
+    let mut bid_orders: [SettleOrder; 10] = [SettleOrder { index: 0, auth: _addr_zero_i64 }; 10];
+    bid_orders[0] = SettleOrder { index: 1, auth: _auth_i64 };
+    // Initialize the ask_orders array
+    let mut ask_orders: [SettleOrder; 10] = [SettleOrder { index: 0, auth: _addr_zero_i64 }; 10];
+    ask_orders[0] = SettleOrder { index: 1, auth: _auth_i64 };
+    let solver_orders = [
+        LimitOrder { max_amnt: 0, price: 0, is_bid: true, addr: _addr2_i64, auth: _auth_i64, next_key: 0 },
+        LimitOrder { max_amnt: 0, price: 0, is_bid: false, addr: _addr2_i64, auth: _auth_i64, next_key: 0 }
+     ];
+    let mut address_list_bid: [[Word; 4]; 11] = [_addr_zero_i64; 11];
+    address_list_bid[0] = _addr0_i64;
+    address_list_bid[10] = _addr2_i64;
+    let mut address_list_ask: [[Word; 4]; 11] = [_addr_zero_i64; 11];
+    address_list_ask[0] = _addr1_i64;
+    address_list_ask[10] = _addr2_i64;
+    let amount_0_final_bid = [0; 11];
+    let mut amount_1_final_bid = [0; 11];
+    amount_1_final_bid[0] = 200;
+    let mut amount_0_final_ask = [0; 11];
+    amount_0_final_ask[0] = 20000;
+    let amount_1_final_ask = [0; 11];
+    let first_bid_order = 0;
+    let first_ask_order = 0;
+    let final_bid_order = [LimitOrder { max_amnt: 0, price: 0, is_bid: false, addr: _addr_zero_i64, auth: _addr_zero_i64, next_key: 0 }; 10];
+    let final_ask_order = [LimitOrder { max_amnt: 0, price: 0, is_bid: false, addr: _addr_zero_i64, auth: _addr_zero_i64, next_key: 0 }; 10];
+    // balances the deposits left, which the settlement's slots start from
+    let mut balances = Balances::new();
+    balances.set(_addr0_i64, 10000, 100);
+    balances.set(_addr1_i64, 10000, 100);
+    let solution0 = produce_solution_settle(
+        100, // partial_amount_bid
+        100, // partial_amount_ask
+        0, // partial_bid_index
+        0, // partial_ask_index
+        bid_orders, // bid_orders: [SettleOrder; 10],
+        ask_orders, // ask_orders: [SettleOrder; 10],
+        solver_orders, // solver_orders: [LimitOrder; 2],
+        &balances, // balances: &Balances,
+        address_list_bid, // address_list: [[Word; 4]; 11], // the last index is the solver address
+        address_list_ask, // address_list: [[Word; 4]; 11], // the last index is the solver address
+        amount_0_final_bid, // amount_0_final: [i64; 11],
+        amount_1_final_bid, // amount_1_final: [i64; 11],
+        amount_0_final_ask, // amount_0_final: [i64; 11],
+        amount_1_final_ask, // amount_1_final: [i64; 11],
+        first_bid_order, // first_bid_order: i64,
+        first_ask_order, // first_ask_order: i64,
+        final_bid_order, // final_bid_order: [LimitOrder; 10],
+        final_ask_order, // final_ask_order: [LimitOrder; 10],
+    ).unwrap();
+    let solution_set = SolutionSet {
+        solutions: vec![solution0],
+    };
+    // println!("solution_set: {:?}", solution_set);
+
+    chain.submit_and_build(solution_set).await.assert_ok();
+    let state = chain.state();
+    let bid_order = state.bid_order(1).await.unwrap();
+     println!("bid_order: {:?}", bid_order);
+     // both orders are filled in full, so their slots are free for new adds again
+     assert!(state.is_vacant(1, true).await.unwrap());
+     assert!(state.is_vacant(1, false).await.unwrap());
+
+     let balance_0_addr0 = state.balance0(_addr0_i64).await.unwrap();
+     let balance_1_addr0 = state.balance1(_addr0_i64).await.unwrap();
+     let balance_0_addr1 = state.balance0(_addr1_i64).await.unwrap();
+     let balance_1_addr1 = state.balance1(_addr1_i64).await.unwrap();
+
+     println!("balance_0_addr0: {:?}", balance_0_addr0);
+     println!("balance_1_addr0: {:?}", balance_1_addr0);
+     println!("balance_0_addr1: {:?}", balance_0_addr1);
+     println!("balance_1_addr1: {:?}", balance_1_addr1);
+ }
+
+ #[tokio::test]
+ async fn test_market_order() {
+        // Convert the addresses for our order
+    let _addr_zero_i64 = hex_to_i64_array("0x0000000000000000000000000000000000000000000000000000000000000000").unwrap();
+    let _addr_i64 = [
+        hex_to_i64_array("0x5B5F934E382FDC4AD1C4AB2448B32BD66B5C53D5A3D5166A9EF48CB6DB3B2B95").unwrap(),
+        hex_to_i64_array("0x7AE73AE363588924F50D5B87F807642B7193D2A0265B451000FAE4318007CD86").unwrap(),
+        hex_to_i64_array("0x5F9C2BD1A47E8039D1A3B687DCE92F33A187E904B61D2A3C9F82C0EF99B72D41").unwrap(),
+        hex_to_i64_array("0x1D3A4F5B7E92834A9C82F7D1E4C73FAD10562E89AC3489F00E217C5DAAB03129").unwrap(),
+        hex_to_i64_array("0xA6B1D47F84392ECBE9D54263A1F73AD40EF73C93D98F4DA51C902B6F776C8BEE").unwrap(),
+        hex_to_i64_array("0x4FAD62397BE8D64CE7B0A3DC129C7A03F56AB0C9D22ADDA2F1EC72D35C7B39A0").unwrap(),
+        hex_to_i64_array("0x936E1C27B9F04A7D01A6B5B193846C903AF45D2379D08A8EB21C75EF9A543621").unwrap(),
+        hex_to_i64_array("0x8B67E24C3DF159A2B4E6D1FA23409C77EFA8BCDA45D6AEF33D19BEA0183F69C4").unwrap(),
+        hex_to_i64_array("0x2D9B7A1ECFA4761BD3C287E4B5F1A8DA01F6C930E8B3AA76F7C13E6DDEF0E111").unwrap(),
+        hex_to_i64_array("0xEC0148A993D273FC7B021D69E3C7A5B1FA98317C9E6D4AB2A03B78E351B3F294").unwrap(),
+    ];        
+    let _auth_word = hex_to_word_array("0x7AE73AE363588924F50D5B87F807642B7193D2A0265B451000FAE4318007CD86").unwrap();
+    let _auth_i64 = hex_to_i64_array("0x7AE73AE363588924F50D5B87F807642B7193D2A0265B451000FAE4318007CD86").unwrap();
+
+    let chain = TestChain::deploy().await;
+    // Next, let's submit a solution
+    // bid address
+    let solution0 = produce_solution_deposit(
+        10000,         //amount_0_delta: i64,
+        10000,         //amount_0_final: i64,
+        100,         //amount_1_delta: i64,
+        100,         //amount_1_final: i64,
+        _addr_i64[0],   //addr_word: [Word; 4],
+        _addr_i64[0],   //key_word: [Word; 4],
+        _auth_word    //auth_word: [Word; 4]
+        );
+    // ask address
+    let solution1 = produce_solution_deposit(
+        10000,         //amount_0_delta: i64,
+        10000,         //amount_0_final: i64,
+        100,         //amount_1_delta: i64,
+        100,         //amount_1_final: i64,
+        _addr_i64[1],   //addr_word: [Word; 4],
+        _addr_i64[1],   //key_word: [Word; 4],
+        _auth_word    //auth_word: [Word; 4]
+    );
+    // bid market order address 1
+    let solution2 = produce_solution_deposit(
+        10000,         //amount_0_delta: i64,
+        10000,         //amount_0_final: i64,
+        100,         //amount_1_delta: i64,
+        100,         //amount_1_final: i64,
+        _addr_i64[2],   //addr_word: [Word; 4],
+        _addr_i64[2],   //key_word: [Word; 4],
+        _auth_word    //auth_word: [Word; 4]
+    );
+    // bid market order address 2
+    let solution3 = produce_solution_deposit(
+        10000,         //amount_0_delta: i64,
+        10000,         //amount_0_final: i64,
+        100,         //amount_1_delta: i64,
+        100,         //amount_1_final: i64,
+        _addr_i64[3],   //addr_word: [Word; 4],
+        _addr_i64[3],   //key_word: [Word; 4],
+        _auth_word    //auth_word: [Word; 4]
+    );
+    // ask market order address 1
+    let solution4 = produce_solution_deposit(
+        10000,         //amount_0_delta: i64,
+        10000,         //amount_0_final: i64,
+        100,         //amount_1_delta: i64,
+        100,         //amount_1_final: i64, 
+        _addr_i64[4],   //addr_word: [Word; 4],
+        _addr_i64[4],   //key_word: [Word; 4],
+        _auth_word    //auth_word: [Word; 4]
+    );
+    // ask market order address 2
+    let solution5 = produce_solution_deposit(
+        10000,         //amount_0_delta: i64,
+        10000,         //amount_0_final: i64,
+        100,         //amount_1_delta: i64,
+        100,         //amount_1_final: i64,
+        _addr_i64[5],   //addr_word: [Word; 4],
+        _addr_i64[5],   //key_word: [Word; 4],
+        _auth_word    //auth_word: [Word; 4]
+    );
+    // solver, which takes up the average price rounding
+    let solution6 = produce_solution_deposit(
+        10000,         //amount_0_delta: i64,
+        10000,         //amount_0_final: i64,
+        100,         //amount_1_delta: i64,
+        100,         //amount_1_final: i64,
+        _addr_i64[6],   //addr_word: [Word; 4],
+        _addr_i64[6],   //key_word: [Word; 4],
+        _auth_word    //auth_word: [Word; 4]
+    );
+    let solution_set = SolutionSet {
+        solutions: vec![solution0, solution1, solution2, solution3, solution4, solution5, solution6],
+    };
+    // println!("solution_set: {:?}", solution_set);
+
+    chain.submit_and_build(solution_set).await.assert_ok();
+
+    let solution0 = produce_solution_add_limit_order_bid(
+        0, // leading_key
+        0, // trailing_key
+        LimitOrder {
+            max_amnt: 100,
+            price: 100,
+            is_bid: true,
+            addr: _addr_i64[0],
+            auth: _auth_i64,
+            next_key: 0,
+        },
+        1, // new_index
+        0, // leading_order_next
+        1, // first_order_index
+    );
+
+    let solution1 = produce_solution_add_limit_order_ask(
+        0, // leading_key
+        0, // trailing_key
+        LimitOrder {
+            max_amnt: 100,
+            price: 100,
+            is_bid: false,
+            addr: _addr_i64[1],
+            auth: _auth_i64,
+            next_key: 0,
+        },
+        1, // new_index
+        0, // leading_order_next    
+        1, // first_order_index
+    );
+
+    let solution_set = SolutionSet {
+        solutions: vec![solution0],
+    };
+    // println!("solution_set: {:?}", solution_set);
+
+    chain.submit_and_build(solution_set).await.assert_ok();
+
+    let solution_set = SolutionSet {
+        solutions: vec![solution1],
+    };
+    // println!("solution_set: {:?}", solution_set);
+
+    chain.submit_and_build(solution_set).await.assert_ok();
+    // Initialize the bid_orders array
+    let mut bid_orders: [SettleOrder; 10] = [SettleOrder { index: 0, auth: _addr_zero_i64 }; 10];
+    bid_orders[0] = SettleOrder { index: 1, auth: _auth_i64 };
+    // Initialize the ask_orders array
+    let mut ask_orders: [SettleOrder; 10] = [SettleOrder { index: 0, auth: _addr_zero_i64 }; 10];
+    ask_orders[0] = SettleOrder { index: 1, auth: _auth_i64 };
+    let mut bid_market_orders: [MarketOrder; 10] = [MarketOrder { amount: 0, addr: _addr_zero_i64, auth: _addr_zero_i64 }; 10];
+    bid_market_orders[0] = MarketOrder { amount: 10, addr: _addr_i64[2], auth: _auth_i64 };
+    bid_market_orders[1] = MarketOrder { amount: 10, addr: _addr_i64[3], auth: _auth_i64 };
+    let mut ask_market_orders: [MarketOrder; 10] = [MarketOrder { amount: 0, addr: _addr_zero_i64, auth: _addr_zero_i64 }; 10];
+    ask_market_orders[0] = MarketOrder { amount: 10, addr: _addr_i64[4], auth: _auth_i64 };
+    ask_market_orders[1] = MarketOrder { amount: 10, addr: _addr_i64[5], auth: _auth_i64 };
+    let solver_orders = [
+        LimitOrder { max_amnt: 0, price: 0, is_bid: true, addr: _addr_i64[6], auth: _auth_i64, next_key: 0 },
+        LimitOrder { max_amnt: 0, price: 0, is_bid: false, addr: _addr_i64[6], auth: _auth_i64, next_key: 0 }
+     ];
+    let mut address_list_bid: [[Word; 4]; 11] = [_addr_zero_i64; 11];
+    address_list_bid[0] = _addr_i64[0];
+    address_list_bid[10] = _addr_i64[6];
+    let mut address_list_ask: [[Word; 4]; 11] = [_addr_zero_i64; 11];
+    address_list_ask[0] = _addr_i64[1];
+    address_list_ask[10] = _addr_i64[6];
+    let mut address_list_bid_market: [[Word; 4]; 10] = [_addr_zero_i64; 10];
+    address_list_bid_market[0] = _addr_i64[2];
+    address_list_bid_market[1] = _addr_i64[3];
+    let mut address_list_ask_market: [[Word; 4]; 10] = [_addr_zero_i64; 10];
+    address_list_ask_market[0] = _addr_i64[4];
+    address_list_ask_market[1] = _addr_i64[5];
+    let mut amount_0_final_bid = [0; 11];
+    let mut amount_1_final_bid = [0; 11];
+    amount_0_final_bid[0] = 8000;
+    amount_1_final_bid[0] = 120;
+    let mut amount_0_final_ask = [0; 11];
+    amount_0_final_ask[0] = 12000;
+    let mut amount_1_final_ask = [0; 11];
+    amount_1_final_ask[0] = 80;
+    // both average prices are exact, so the solver's balances stay as they are
+    amount_0_final_bid[10] = 10000;
+    amount_1_final_bid[10] = 100;
+    amount_0_final_ask[10] = 10000;
+    amount_1_final_ask[10] = 100;
+    let mut amount_0_final_bid_market = [0; 10];
+    amount_0_final_bid_market[0] = 9000;
+    amount_0_final_bid_market[1] = 9000;
+    let mut amount_1_final_bid_market = [0; 10];
+    amount_1_final_bid_market[0] = 110;
+    amount_1_final_bid_market[1] = 110;
+    let mut amount_0_final_ask_market = [0; 10];
+    amount_0_final_ask_market[0] = 11000;
+    amount_0_final_ask_market[1] = 11000;
+    let mut amount_1_final_ask_market = [0; 10];
+    amount_1_final_ask_market[0] = 90;
+    amount_1_final_ask_market[1] = 90;
+    let first_bid_order = 1;
+    let first_ask_order = 1;
+    let mut final_bid_order = [LimitOrder { max_amnt: 0, price: 0, is_bid: false, addr: _addr_zero_i64, auth: _addr_zero_i64, next_key: 0 }; 10];
+    final_bid_order[0] = LimitOrder { max_amnt: 80, price: 100, is_bid: true, addr: _addr_i64[0], auth: _auth_i64, next_key: 0 };
+    let mut final_ask_order = [LimitOrder { max_amnt: 0, price: 0, is_bid: false, addr: _addr_zero_i64, auth: _addr_zero_i64, next_key: 0 }; 10];
+    final_ask_order[0] = LimitOrder { max_amnt: 80, price: 100, is_bid: false, addr: _addr_i64[1], auth: _auth_i64, next_key: 0 };
+    // every address deposited the same, which the settlement's slots start from
+    let mut balances = Balances::new();
+    for addr in &_addr_i64[..7] {
+        balances.set(*addr, 10000, 100);
+    }
+    let solution0 = produce_solution_market_order(
+        20, // partial_amount_bid
+        20, // partial_amount_ask
+        0, // partial_bid_index
+        0, // partial_ask_index
+        bid_orders, // bid_orders: [SettleOrder; 10],
+        ask_orders, // ask_orders: [SettleOrder; 10],
+        bid_market_orders, // bid_market_orders: [MarketOrder; 10],
+        ask_market_orders, // ask_market_orders: [MarketOrder; 10],
+        100, // average_price_bid
+        100, // average_price_ask
+        solver_orders, // solver_orders: [LimitOrder; 2],
+        &balances, // balances: &Balances,
+        address_list_bid, // address_list: [[Word; 4]; 11], // the last index is the solver address
+        address_list_ask, // address_list: [[Word; 4]; 11], // the last index is the solver address
+        address_list_bid_market, // address_list_bid_market: [[Word; 4]; 10],
+        address_list_ask_market, // address_list_ask_market: [[Word; 4]; 10],
+        amount_0_final_bid, // amount_0_final: [i64; 11],
+        amount_1_final_bid, // amount_1_final: [i64; 11],
+        amount_0_final_ask, // amount_0_final: [i64; 11],
+        amount_1_final_ask, // amount_1_final: [i64; 11],
+        amount_0_final_bid_market, // amount_0_final_bid_market: [i64; 10],
+        amount_1_final_bid_market, // amount_1_final_bid_market: [i64; 10],
+        amount_0_final_ask_market, // amount_0_final_ask_market: [i64; 10],
+        amount_1_final_ask_market, // amount_1_final_ask_market: [i64; 10],
+        first_bid_order, // first_bid_order_market: i64,
+        first_ask_order, // first_ask_order_market: i64,
+        final_bid_order, // final_bid_order_market: [LimitOrder; 10],
+        final_ask_order, // final_ask_order_market: [LimitOrder; 10],            
+    ).unwrap();
+    let solution_set = SolutionSet {
+        solutions: vec![solution0],
+    };
+    // println!("solution_set: {:?}", solution_set);
+
+    chain.submit_and_build(solution_set).await.assert_ok();
+    let state = chain.state();
+    let bid_order = state.bid_order(1).await.unwrap();
+     println!("bid_order: {:?}", bid_order);
+
+     let balance_0_addr_bid = state.balance0(_addr_i64[0]).await.unwrap();
+     let balance_1_addr_bid = state.balance1(_addr_i64[0]).await.unwrap();
+     let balance_0_addr_ask = state.balance0(_addr_i64[1]).await.unwrap();
+     let balance_1_addr_ask = state.balance1(_addr_i64[1]).await.unwrap();
+     let balance_0_addr_bid_market0 = state.balance0(_addr_i64[2]).await.unwrap();
+     let balance_1_addr_bid_market0 = state.balance1(_addr_i64[2]).await.unwrap();
+     let balance_0_addr_bid_market1 = state.balance0(_addr_i64[3]).await.unwrap();
+     let balance_1_addr_bid_market1 = state.balance1(_addr_i64[3]).await.unwrap();
+     let balance_0_addr_ask_market0 = state.balance0(_addr_i64[4]).await.unwrap();
+     let balance_1_addr_ask_market0 = state.balance1(_addr_i64[4]).await.unwrap();
+     let balance_0_addr_ask_market1 = state.balance0(_addr_i64[5]).await.unwrap();
+     let balance_1_addr_ask_market1 = state.balance1(_addr_i64[5]).await.unwrap();
+     
+     println!("balance_0_addr_bid: {:?}", balance_0_addr_bid);
+     println!("balance_1_addr_bid: {:?}", balance_1_addr_bid);
+     println!("balance_0_addr_ask: {:?}", balance_0_addr_ask);
+     println!("balance_1_addr_ask: {:?}", balance_1_addr_ask);
+     println!("balance_0_addr_bid_market0: {:?}", balance_0_addr_bid_market0);
+     println!("balance_1_addr_bid_market0: {:?}", balance_1_addr_bid_market0);
+     println!("balance_0_addr_bid_market1: {:?}", balance_0_addr_bid_market1);
+     println!("balance_1_addr_bid_market1: {:?}", balance_1_addr_bid_market1);
+     println!("balance_0_addr_ask_market0: {:?}", balance_0_addr_ask_market0);
+     println!("balance_1_addr_ask_market0: {:?}", balance_1_addr_ask_market0);
+     println!("balance_0_addr_ask_market1: {:?}", balance_0_addr_ask_market1);
+     println!("balance_1_addr_ask_market1: {:?}", balance_1_addr_ask_market1);
+
+     // the solver's balances are written back unchanged and, all together, the traders and
+     // the solver still hold everything they deposited
+     assert_eq!(state.balance0(_addr_i64[6]).await.unwrap(), 10000);
+     assert_eq!(state.balance1(_addr_i64[6]).await.unwrap(), 100);
+     let mut total_0 = 0;
+     let mut total_1 = 0;
+     for addr in &_addr_i64[..7] {
+         total_0 += state.balance0(*addr).await.unwrap();
+         total_1 += state.balance1(*addr).await.unwrap();
+     }
+     assert_eq!((total_0, total_1), (70000, 700));
+}
+#[tokio::test]
+async fn test_experiment_trace() {
+    /* 
+    In this test, we will run a large experiment trace.
+    Step 1: Generate n addresses.
+    Step 2: Generate a sequence of prices, p_1, p_2, ..., p_m. Here we assume m = 100. Also, |p_i - p_i+1| = 1. So, p(t) becomes a random walk.
+    And the price sequence is generated by a function price_seq(t).
+    Step 3: Deposit k = 1000,000 tokens to all addresses. 
+    Step 4: For each time step t, first, settlement of existing limit orders happens based on current price p(t). Then, market orders are settled.
+    Then, limit orders are added such that the bid orders go from p(t) to p(t) - 1, and the ask orders go from p(t) to p(t) + 1.
+    Step 5: Repeat Step 4 for m times.
+    */
+    let n = 1000; // number of addresses
+    let _k = 1000000; // initial amount of tokens in each address
+    let m = 20; // number of price steps
+    let mut balances = Balances::new();
+    // Step 1: generate n addresses
+
+    // Use a fixed seed for reproducibility
+    let seed: u64 = 42;
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    for _ in 0..10 {
+        println!("{:?}", hex_to_i64_array(generate_random_hash(&mut rng).as_str()).unwrap());
+    }
+
+    let mut addresses = vec![];
+    for _ in 0..n {
+        addresses.push(hex_to_i64_array(generate_random_hash(&mut rng).as_str()).unwrap());
+    }
+    let _addr_zero_i64 = hex_to_i64_array("0x0000000000000000000000000000000000000000000000000000000000000000").unwrap();
+    // Step 2: generate a random walk price sequence
+    let mut price_seq = vec![0i64; m];
+    price_seq[0] = 100;
+
+    for i in 1..m {
+        let step: i64 = if rng.gen_bool(0.6) { 5 } else { -5 };
+        price_seq[i] = (price_seq[i - 1] + step).max(0);
+    }
+
+    // Optional: print the first few values
+    for price in price_seq.iter().take(100) {
+        println!("{}", price);
+    }
+
+    // Step 3: deposit k = 1000,000 tokens to all addresses
+    
+    let chain = TestChain::deploy().await;
+   
+    // deposits for distinct addresses don't conflict, so they go in a handful of blocks
+    let mut deposits = vec![];
+    for addr in &addresses {
+        let solution = produce_solution_deposit(
+            1000000,         //amount_0_delta: i64,
+            1000000,         //amount_0_final: i64,
+            1000000,         //amount_1_delta: i64,
+            1000000,         //amount_1_final: i64,
+            *addr,   //addr_word: [Word; 4],
+            *addr,   //key_word: [Word; 4],
+            *addr    //auth_word: [Word; 4]
+            );
+        deposits.push(solution);
+        balances.set(*addr, 1_000_000, 1_000_000);
+    }
+    chain.submit_batched(deposits).await.assert_ok();
+
+    // Step 4.1: For each time step t, first, settlement of existing limit orders happens based on current price p(t).
+    // Then, limit orders are added such that the bid orders go from p(t) to p(t) - 1, and the ask orders go from p(t) to p(t) + 1.
+
+    // bids is a BTreeMap of price -> PriceLevel, where PriceLevel is a VecDeque of Order
+    // asks is a BTreeMap of price -> PriceLevel, where PriceLevel is a VecDeque of Order
+    // PriceLevel is always sorted by time-priority, i.e. the oldest order is at the front
+    let mut orderbook = OrderBook {
+        bids: BTreeMap::new(),
+        asks: BTreeMap::new(),
+    };
+    // slots of settled orders are handed out again, so re-adding to cleared slots is covered
+    let mut ids = OrderIdAllocator::new(IdScheme::Deterministic { seed: 42 });
+    for &price in price_seq.iter().take(m) {
+        let current_price = price as u64;
+        println!("price: {:?}", current_price);
+        // settlement of existing limit orders:
+
+        // every bid at or above p(t) and every ask at or below it is settled against the solver
+        let report = sweep(&orderbook, current_price as i64);
+        let solver_addr = *addresses.iter().next_back().unwrap();
+        let plan = plan_settle(&orderbook, &balances, &report.bids, &report.asks, solver_addr).unwrap();
+        for chunk in &plan.chunks {
+            println!("partial_bid_index: {:?}, partial_bid_amount: {:?}", chunk.partial_bid_index(), chunk.partial_amount_bid);
+            println!("partial_ask_index: {:?}, partial_ask_amount: {:?}", chunk.partial_ask_index(), chunk.partial_amount_ask);
+            println!("solver_orders: {:?}", chunk.solver_orders);
+            println!("first_bid_order: {:?}", chunk.first_bid_order);
+            println!("first_ask_order: {:?}", chunk.first_ask_order);
+            let solution_set = SolutionSet {
+                solutions: vec![chunk.to_solution().unwrap()],
+            };
+        
+            chain.submit_and_build(solution_set).await.assert_ok();
+            chunk.apply(&mut orderbook, &mut balances);
+            ids.reclaim(&orderbook, &chain.state(), chunk.bids.iter().chain(&chunk.asks).map(|leg| leg.order.index)).await.unwrap();
+        }
+
+        // add new limit orders as one ladder, every level linked in by the same solution set
+        let mut ladder = vec![];
+        // fill all the asks from p(t) onwards and above
+        // fill all the bids from p(t) onwards and below
+
+        // bids
+        // Fill bid gaps: current_price ↓ to lowest existing bid
+        let highest_bid_price = if let Some((&_price, _)) = orderbook.bids.iter().next_back() {
+            _price + 1
+        } else {
+            current_price - 1
+        };
+        if current_price >= highest_bid_price {
+            for price in highest_bid_price..=current_price {
+                println!("price: {:?}", price);
+                if !orderbook.bids.contains_key(&price) {
+                    for _ in 0..3 {
+                        let _addr = addresses.pop().unwrap();
+                        ladder.push(Order {
+                            index: ids.next(&orderbook, _addr).unwrap(),
+                            max_amnt: 100,
+                            price: price as i64,
+                            is_bid: true,
+                            addr: _addr,
+                            auth: _addr_zero_i64,
+                        });
+                    }
+                }
+            }
+        }
+
+        // Fill ask gaps: current_price ↑ to highest existing ask
+        let lowest_ask_price = if let Some((&_price, _)) = orderbook.asks.iter().next() {
+            _price - 1
+        } else {
+            current_price + 1
+        };
+        if current_price <= lowest_ask_price {
+            for price in (current_price..=lowest_ask_price).rev() {
+                if !orderbook.asks.contains_key(&price) {
+                    for _ in 0..3 {
+                        let _addr = addresses.pop().unwrap();
+                        ladder.push(Order {
+                            index: ids.next(&orderbook, _addr).unwrap(),
+                            max_amnt: 100,
+                            price: price as i64,
+                            is_bid: false,
+                            addr: _addr,
+                            auth: _addr_zero_i64,
+                        });
+                    }
+                }
+            }
+        }
+
+        let plan = orderbook.plan_ladder(ladder).unwrap();
+        println!("ladder plan: {:?}", plan.runs);
+        // runs longer than INSERT_RUN spill into another block
+        for solution_set in plan.to_solution_sets().unwrap() {
+            chain.submit_and_build(solution_set).await.assert_ok();
+        }
+        plan.apply(&mut orderbook);
+
+        let state = chain.state();
+        let report = reconcile(&state, &orderbook, &balances).await.unwrap();
+        assert!(report.is_consistent(), "{}", report);
+    }
+}
+
+// Replays the sample trace and checks every step's on-chain book against the trace.
+#[tokio::test]
+async fn test_replay_trace() {
+    let trace_path = concat!(env!("CARGO_MANIFEST_DIR"), "/traces/sample.txt");
+    let (steps, price_seq) = parse_orderbook_file(trace_path).await.unwrap();
+    println!("{} steps, prices: {:?}", steps.len(), price_seq);
+
+    let chain = TestChain::deploy().await;
+
+    let mut rng = StdRng::seed_from_u64(7);
+    let makers = (0..4).map(|_| hex_to_i64_array(&generate_random_hash(&mut rng)).unwrap()).collect();
+    let solver_addr = hex_to_i64_array(&generate_random_hash(&mut rng)).unwrap();
+    let mut replay = Replay::new(&chain.dbs, chain.variant.clone(), makers, solver_addr).unwrap();
+    replay.fund(1_000_000, 1_000_000).await.unwrap();
+
+    for (t, report) in replay.run(&steps).await.unwrap().iter().enumerate() {
+        println!("step {}: {:?}", t, report);
+        assert!(report.mismatches.is_empty(), "step {}: {:?}", t, report.mismatches);
+    }
+}
+
+// An overdrawing withdraw is rejected and leaves the deposit in place.
+#[tokio::test]
+async fn test_rejected_withdraw() {
+    let addr = hex_to_i64_array("0x5B5F934E382FDC4AD1C4AB2448B32BD66B5C53D5A3D5166A9EF48CB6DB3B2B95").unwrap();
+    let chain = TestChain::deploy().await;
+
+    let deposit = produce_solution_deposit(100, 100, 10, 10, addr, addr, addr);
+    chain.submit_and_build(SolutionSet { solutions: vec![deposit] }).await.assert_ok();
+
+    let withdraw = produce_solution_withdraw(150, -50, 0, 10, addr, addr, addr);
+    let outcome = chain.submit_and_build(SolutionSet { solutions: vec![withdraw] }).await;
+    outcome.assert_failed();
+    assert!(outcome.report.contains("withdraw"), "{}", outcome.report);
+    assert_eq!(chain.state().balance0(addr).await.unwrap(), 100);
+}
+
+// Three bids at one level and a ladder of asks go in with a single solution set.
+#[tokio::test]
+async fn test_add_limit_order_ladder() {
+    let addr = hex_to_i64_array("0x5B5F934E382FDC4AD1C4AB2448B32BD66B5C53D5A3D5166A9EF48CB6DB3B2B95").unwrap();
+    let chain = TestChain::deploy().await;
+    let mut balances = Balances::new();
+    let deposit = produce_solution_deposit(1_000_000, 1_000_000, 1_000, 1_000, addr, addr, addr);
+    chain.submit_and_build(SolutionSet { solutions: vec![deposit] }).await.assert_ok();
+    balances.set(addr, 1_000_000, 1_000);
+
+    let mut orderbook = OrderBook::new();
+    let order = |index, price, is_bid| Order { index, max_amnt: 10, price, is_bid, addr, auth: addr };
+    let resting = orderbook.plan_insert(order(1, 100, true)).unwrap();
+    chain.submit_and_build(SolutionSet { solutions: vec![resting.to_solution()] }).await.assert_ok();
+    resting.apply(&mut orderbook);
+
+    let ladder = vec![order(2, 99, true), order(3, 99, true), order(4, 99, true), order(5, 101, false), order(6, 102, false)];
+    let plan = orderbook.plan_ladder(ladder).unwrap();
+    assert!(plan.is_atomic());
+    let solution_sets = plan.to_solution_sets().unwrap();
+    assert_eq!(solution_sets.len(), 1);
+    chain.submit_and_build(solution_sets[0].clone()).await.assert_ok();
+    plan.apply(&mut orderbook);
+
+    let report = reconcile(&chain.state(), &orderbook, &balances).await.unwrap();
+    assert!(report.is_consistent(), "{}", report);
+    assert_eq!(orderbook.chain(true).iter().map(|o| o.index).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+}
+
+// Several fills, one trader with two bids and one on both sides of the book, settle over
+// two chunks. Every solution writes each balance once and the chain ends at the balances
+// the plan computed.
+#[tokio::test]
+async fn test_settle_writes_each_balance_once() {
+    let chain = TestChain::deploy().await;
+    let traders: Vec<[Word; 4]> = (1..=4).map(|i| [i, 0, 0, 0]).collect();
+    let solver_addr = [9, 0, 0, 0];
+    let mut balances = Balances::new();
+    let deposits = traders
+        .iter()
+        .chain([&solver_addr])
+        .map(|&addr| {
+            balances.set(addr, 100_000, 1_000);
+            produce_solution_deposit(100_000, 100_000, 1_000, 1_000, addr, addr, addr)
+        })
+        .collect();
+    chain.submit_batched(deposits).await.assert_ok();
+
+    let mut orderbook = OrderBook::new();
+    let orders = [(1, 101, true, 0), (2, 100, true, 0), (3, 100, true, 1), (4, 99, false, 2), (5, 99, false, 3), (6, 99, false, 1)];
+    for (index, price, is_bid, trader) in orders {
+        let addr = traders[trader];
+        let plan = orderbook.plan_insert(Order { index, max_amnt: 10, price, is_bid, addr, auth: addr }).unwrap();
+        chain.submit_and_build(SolutionSet { solutions: vec![plan.to_solution()] }).await.assert_ok();
+        plan.apply(&mut orderbook);
+    }
+
+    let fills = match_book(&orderbook);
+    let plan = plan_settle(&orderbook, &balances, &fills.bids, &fills.asks, solver_addr).unwrap();
+    assert_eq!(plan.chunks.len(), 2);
+    let zero = [0; 4];
+    let balance_keys: HashSet<Key> = traders
+        .iter()
+        .chain([&solver_addr, &zero])
+        .flat_map(|&addr| [balances_0_key(addr).unwrap(), balances_1_key(addr).unwrap()])
+        .collect();
+    for chunk in &plan.chunks {
+        let solution = chunk.to_solution().unwrap();
+        let written: Vec<&Key> = solution.state_mutations.iter().map(|m| &m.key).filter(|key| balance_keys.contains(*key)).collect();
+        assert_eq!(written.len(), written.iter().collect::<HashSet<_>>().len());
+        chain.submit_and_build(SolutionSet { solutions: vec![solution] }).await.assert_ok();
+        chunk.apply(&mut orderbook, &mut balances);
+    }
+
+    assert_eq!(balances, plan.balances);
+    let report = reconcile(&chain.state(), &orderbook, &balances).await.unwrap();
+    assert!(report.is_consistent(), "{}", report);
+    let state = chain.state();
+    assert_eq!((state.balance0(traders[0]).await.unwrap(), state.balance1(traders[0]).await.unwrap()), (100_000 - 2_010, 1_020));
+    assert_eq!((state.balance0(traders[1]).await.unwrap(), state.balance1(traders[1]).await.unwrap()), (100_000 - 10, 1_000));
+}
+
+// One trader's two bids both fill. The predicate checks every slot's balances on their
+// own, so a solution holding both is refused before it is built, and the plan settles them
+// one chunk after the other.
+#[tokio::test]
+async fn test_settle_refuses_a_trader_twice_in_one_solution() {
+    let chain = TestChain::deploy().await;
+    let (buyer, seller, solver_addr) = ([1, 0, 0, 0], [2, 0, 0, 0], [9, 0, 0, 0]);
+    let mut balances = Balances::new();
+    let deposits = [buyer, seller, solver_addr]
+        .into_iter()
+        .map(|addr| {
+            balances.set(addr, 100_000, 1_000);
+            produce_solution_deposit(100_000, 100_000, 1_000, 1_000, addr, addr, addr)
+        })
+        .collect();
+    chain.submit_batched(deposits).await.assert_ok();
+
+    let mut orderbook = OrderBook::new();
+    for (index, max_amnt, is_bid, addr) in [(1, 10, true, buyer), (2, 10, true, buyer), (3, 20, false, seller)] {
+        let plan = orderbook.plan_insert(Order { index, max_amnt, price: 100, is_bid, addr, auth: addr }).unwrap();
+        chain.submit_and_build(SolutionSet { solutions: vec![plan.to_solution()] }).await.assert_ok();
+        plan.apply(&mut orderbook);
+    }
+
+    let fills = match_book(&orderbook);
+    let plan = plan_settle(&orderbook, &balances, &fills.bids, &fills.asks, solver_addr).unwrap();
+    assert_eq!(plan.chunks.len(), 2);
+    let mut twice = plan.chunks[0].clone();
+    twice.bids.push(plan.chunks[1].bids[0].clone());
+    assert!(matches!(twice.to_solution(), Err(SolverError::Validation(_))));
+
+    for chunk in &plan.chunks {
+        chain.submit_and_build(SolutionSet { solutions: vec![chunk.to_solution().unwrap()] }).await.assert_ok();
+        chunk.apply(&mut orderbook, &mut balances);
+    }
+    let report = reconcile(&chain.state(), &orderbook, &balances).await.unwrap();
+    assert!(report.is_consistent(), "{}", report);
+    let state = chain.state();
+    assert_eq!((state.balance0(buyer).await.unwrap(), state.balance1(buyer).await.unwrap()), (100_000 - 2_000, 1_020));
+}
+
+// A contract settling three orders per side takes a plan made for that width, chunk by
+// chunk, and the standard contract's width is refused.
+#[tokio::test]
+async fn test_settle_narrow_batch() {
+    let chain = TestChain::deploy_batch(3).await;
+    let traders: Vec<[Word; 4]> = (1..=8).map(|i| [i, 0, 0, 0]).collect();
+    let solver_addr = [9, 0, 0, 0];
+    let mut balances = Balances::new();
+    let deposits = traders
+        .iter()
+        .chain([&solver_addr])
+        .map(|&addr| {
+            balances.set(addr, 100_000, 1_000);
+            chain.variant.retarget(produce_solution_deposit(100_000, 100_000, 1_000, 1_000, addr, addr, addr)).unwrap()
+        })
+        .collect();
+    chain.submit_batched(deposits).await.assert_ok();
+
+    let mut orderbook = OrderBook::new();
+    for (i, &addr) in traders.iter().enumerate() {
+        let is_bid = i < 4;
+        let plan = orderbook.plan_insert(Order { index: i as i64 + 1, max_amnt: 10, price: 100, is_bid, addr, auth: addr }).unwrap();
+        let solution = chain.variant.retarget(plan.to_solution()).unwrap();
+        chain.submit_and_build(SolutionSet { solutions: vec![solution] }).await.assert_ok();
+        plan.apply(&mut orderbook);
+    }
+
+    let fills = match_book(&orderbook);
+    let plan = plan_settle_with(&orderbook, &balances, &fills.bids, &fills.asks, solver_addr, &Vwap, 3).unwrap();
+    assert_eq!(plan.chunks.iter().map(|c| (c.bids.len(), c.asks.len())).collect::<Vec<_>>(), vec![(3, 3), (1, 1)]);
+    assert!(matches!(plan.solutions(), Err(SolverError::Validation(_))));
+    for (chunk, solution) in plan.chunks.iter().zip(plan.solutions_for(&chain.variant).unwrap()) {
+        chain.submit_and_build(SolutionSet { solutions: vec![solution] }).await.assert_ok();
+        chunk.apply(&mut orderbook, &mut balances);
+    }
+
+    assert!(orderbook.chain(true).is_empty() && orderbook.chain(false).is_empty());
+    let report = reconcile(&chain.state(), &orderbook, &balances).await.unwrap();
+    assert!(report.is_consistent(), "{}", report);
+}
+
+#[tokio::test]
+async fn test_readd_at_reclaimed_index() {
+    let chain = TestChain::deploy().await;
+    let (buyer, seller, solver_addr) = ([1, 0, 0, 0], [2, 0, 0, 0], [9, 0, 0, 0]);
+    let mut balances = Balances::new();
+    let deposits = [buyer, seller, solver_addr]
+        .iter()
+        .map(|&addr| {
+            balances.set(addr, 100_000, 1_000);
+            produce_solution_deposit(100_000, 100_000, 1_000, 1_000, addr, addr, addr)
+        })
+        .collect();
+    chain.submit_batched(deposits).await.assert_ok();
+
+    let mut orderbook = OrderBook::new();
+    let mut ids = OrderIdAllocator::new(IdScheme::Monotonic);
+    let add = async |orderbook: &mut OrderBook, ids: &mut OrderIdAllocator, price, is_bid, addr| {
+        let index = ids.next_free(orderbook, &chain.state(), addr).await.unwrap();
+        let plan = orderbook.plan_insert(Order { index, max_amnt: 10, price, is_bid, addr, auth: addr }).unwrap();
+        chain.submit_and_build(SolutionSet { solutions: vec![plan.to_solution()] }).await.assert_ok();
+        plan.apply(orderbook);
+        index
+    };
+    assert_eq!(add(&mut orderbook, &mut ids, 100, true, buyer).await, 1);
+    assert_eq!(add(&mut orderbook, &mut ids, 99, true, buyer).await, 2);
+    assert_eq!(add(&mut orderbook, &mut ids, 100, false, seller).await, 3);
+
+    // 1 and 3 fill each other in full, 2 rests
+    let fills = match_book(&orderbook);
+    let plan = plan_settle(&orderbook, &balances, &fills.bids, &fills.asks, solver_addr).unwrap();
+    for chunk in &plan.chunks {
+        chain.submit_and_build(SolutionSet { solutions: vec![chunk.to_solution().unwrap()] }).await.assert_ok();
+        chunk.apply(&mut orderbook, &mut balances);
+    }
+    // a removed order's slot is zeroed, not cleared, so no add can take it again
+    let cancel = orderbook.plan_cancel(2, true).unwrap();
+    chain.submit_and_build(SolutionSet { solutions: vec![cancel.to_solution()] }).await.assert_ok();
+    ids.reclaim(&orderbook, &chain.state(), [1, 2, 3]).await.unwrap();
+
+    assert_eq!(add(&mut orderbook, &mut ids, 100, false, seller).await, 1);
+    assert_eq!(add(&mut orderbook, &mut ids, 101, true, buyer).await, 3);
+    assert_eq!(add(&mut orderbook, &mut ids, 98, true, buyer).await, 4);
+    assert!(reconcile(&chain.state(), &orderbook, &balances).await.unwrap().is_consistent());
+}
+
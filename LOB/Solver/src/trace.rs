@@ -0,0 +1,97 @@
+//! Parser for recorded orderbook traces.
+use crate::error::{Result, SolverError};
+use crate::fixed::Decimals;
+use regex::Regex;
+use std::collections::BTreeMap;
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+/// Book deltas and mid price of one step of a trace, in on-chain units (see [`Decimals`]).
+#[derive(Debug)]
+pub struct OrderBookParse {
+    pub bids: BTreeMap<u64, i64>,
+    pub asks: BTreeMap<u64, i64>,
+    pub middle_price: i64,
+}
+
+/// Parses a trace made of `step:`, `price:`, `delta in bids: [...]` and `delta in asks: [...]`
+/// lines. Returns one `OrderBookParse` per step together with the sequence of mid prices.
+/// Prices and quantities use [`Decimals::legacy`], i.e. both are scaled by 10.
+pub async fn parse_orderbook_file(file_path: &str) -> Result<(Vec<OrderBookParse>, Vec<i64>)> {
+    parse_orderbook_file_with(file_path, &Decimals::legacy()).await
+}
+
+/// Like [`parse_orderbook_file`], converting prices and quantities with `decimals`.
+pub async fn parse_orderbook_file_with(
+    file_path: &str,
+    decimals: &Decimals,
+) -> Result<(Vec<OrderBookParse>, Vec<i64>)> {
+    let file = File::open(file_path).await?;
+    let reader = BufReader::new(file);
+    let mut lines = reader.lines();
+
+    let mut orderbooks = Vec::new();
+    let mut price_seq = Vec::new();
+    let mut current_price: Option<i64> = None;
+    let mut current = OrderBookParse {
+        bids: BTreeMap::new(),
+        asks: BTreeMap::new(),
+        middle_price: 0,
+    };
+
+    let re_bids = Regex::new(r"^delta in bids: \[(.*)\]").unwrap();
+    let re_asks = Regex::new(r"^delta in asks: \[(.*)\]").unwrap();
+    let re_price = Regex::new(r"^price: ([\d\.]+)").unwrap();
+    let tuple_re = Regex::new(r"\(([\d.]+), ([\d.]+)\)").unwrap();
+
+    while let Some(line) = lines.next_line().await? {
+        let line = line.trim().to_string();
+
+        if line.starts_with("step:") {
+            if !current.bids.is_empty() || !current.asks.is_empty() {
+                if let Some(p) = current_price {
+                    current.middle_price = p;
+                    price_seq.push(p);
+                }
+                orderbooks.push(current);
+                current = OrderBookParse {
+                    bids: BTreeMap::new(),
+                    asks: BTreeMap::new(),
+                    middle_price: 0,
+                };
+                current_price = None;
+            }
+        } else if let Some(caps) = re_price.captures(&line) {
+            current_price = Some(decimals.price_to_chain(parse_number(&caps[1])?)?);
+        } else if let Some(caps) = re_bids.captures(&line) {
+            let content = &caps[1];
+            for tup in tuple_re.captures_iter(content) {
+                let price = decimals.price_to_chain(parse_number(&tup[1])?)?;
+                let qty = decimals.quantity_to_chain(parse_number(&tup[2])?)?;
+                current.bids.insert(price as u64, qty);
+            }
+        } else if let Some(caps) = re_asks.captures(&line) {
+            let content = &caps[1];
+            for tup in tuple_re.captures_iter(content) {
+                let price = decimals.price_to_chain(parse_number(&tup[1])?)?;
+                let qty = decimals.quantity_to_chain(parse_number(&tup[2])?)?;
+                current.asks.insert(price as u64, qty);
+            }
+        }
+    }
+
+    if !current.bids.is_empty() || !current.asks.is_empty() {
+        if let Some(p) = current_price {
+            current.middle_price = p;
+            price_seq.push(p);
+        }
+        orderbooks.push(current);
+    }
+
+    Ok((orderbooks, price_seq))
+}
+
+fn parse_number(s: &str) -> Result<f64> {
+    s.parse()
+        .map_err(|_| SolverError::Decode(format!("invalid number {:?} in trace", s)))
+}
@@ -0,0 +1,323 @@
+//! Orderbook contracts with a different settlement batch size.
+//!
+//! The contract's `settle` and `settleMarketOrders` predicates take `settle_order[10]` and
+//! `market_order[10]`, and pick the partially filled order with a ten-armed `cond`;
+//! `addLimitOrdersBid` and `addLimitOrdersAsk` take runs of `add_order[5]`.
+//! [`render_source`] rewrites all of them for any batch size and run length and
+//! [`write_project`] writes the result next to the original project, so an N-wide contract
+//! can be compiled and deployed like the standard one.
+//!
+//! Changing any predicate changes the contract's content address and therefore every
+//! predicate address. A [`ContractVariant`] holds the addresses of one deployed contract;
+//! [`ContractVariant::retarget`] points a solution built with the `crate::abi` addresses
+//! at it, and the `*_batch` builders in [`crate::solutions`] take it directly.
+use crate::abi::{addLimitOrderAsk, addLimitOrderAskBehind, addLimitOrderBid, addLimitOrderBidBehind, addLimitOrdersAsk, addLimitOrdersBid, deposit, removeLimitOrderAsk, removeLimitOrderBid, settle, settleMarketOrders, withdraw};
+use crate::diagnostics::predicate_name;
+use crate::error::{Result, SolverError};
+use crate::settlement::SETTLE_BATCH;
+use crate::solutions::INSERT_RUN;
+use essential_types::{ContentAddress, PredicateAddress, contract::Contract, solution::Solution};
+use pint_abi::types::{ContractABI, TypeABI};
+use regex::Regex;
+use std::path::{Path, PathBuf};
+
+/// Predicate addresses, batch size and run length of one deployed orderbook contract.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContractVariant {
+    /// Orders per side `settle` and `settleMarketOrders` take.
+    pub batch: usize,
+    /// Most new orders `addLimitOrdersBid` and `addLimitOrdersAsk` link in.
+    pub insert_run: usize,
+    pub contract: ContentAddress,
+    predicates: Vec<(String, PredicateAddress)>,
+    abi: Option<ContractABI>,
+}
+
+impl ContractVariant {
+    /// The contract `crate::abi` was generated from, with [`SETTLE_BATCH`] wide settlement
+    /// and runs of [`INSERT_RUN`].
+    pub fn standard() -> Self {
+        let predicates = [
+            ("deposit", deposit::ADDRESS),
+            ("withdraw", withdraw::ADDRESS),
+            ("addLimitOrderBid", addLimitOrderBid::ADDRESS),
+            ("addLimitOrderBidBehind", addLimitOrderBidBehind::ADDRESS),
+            ("addLimitOrderAsk", addLimitOrderAsk::ADDRESS),
+            ("addLimitOrderAskBehind", addLimitOrderAskBehind::ADDRESS),
+            ("removeLimitOrderBid", removeLimitOrderBid::ADDRESS),
+            ("removeLimitOrderAsk", removeLimitOrderAsk::ADDRESS),
+            ("addLimitOrdersBid", addLimitOrdersBid::ADDRESS),
+            ("addLimitOrdersAsk", addLimitOrdersAsk::ADDRESS),
+            ("settle", settle::ADDRESS),
+            ("settleMarketOrders", settleMarketOrders::ADDRESS),
+        ];
+        Self {
+            batch: SETTLE_BATCH,
+            insert_run: INSERT_RUN,
+            contract: settle::ADDRESS.contract,
+            predicates: predicates.into_iter().map(|(name, address)| (name.to_string(), address)).collect(),
+            abi: None,
+        }
+    }
+
+    /// The variant compiled into `contract`. `abi_json` is the ABI pint wrote next to it,
+    /// whose predicates are listed in the same order as the contract's, and which gives
+    /// the run length.
+    pub fn from_compiled(contract: &Contract, abi_json: &str, batch: usize) -> Result<Self> {
+        let abi: ContractABI = serde_json::from_str(abi_json)
+            .map_err(|e| SolverError::Deploy(format!("invalid contract ABI: {}", e)))?;
+        if abi.predicates.len() != contract.predicates.len() {
+            return Err(SolverError::Deploy(format!(
+                "ABI lists {} predicates but the contract has {}",
+                abi.predicates.len(),
+                contract.predicates.len()
+            )));
+        }
+        let address = essential_hash::contract_addr::from_contract(contract);
+        let predicates = abi
+            .predicates
+            .iter()
+            .zip(&contract.predicates)
+            .map(|(abi, predicate)| {
+                let name = abi.name.trim_start_matches("::").to_string();
+                let predicate = essential_hash::content_addr(predicate);
+                (name, PredicateAddress { contract: address.clone(), predicate })
+            })
+            .collect();
+        let insert_run = insert_run(&abi)?;
+        Ok(Self { batch, insert_run, contract: address, predicates, abi: Some(abi) })
+    }
+
+    /// The variant compiled from the pint project at `project`, reading the ABI `pint build`
+    /// wrote into its `out/debug` directory.
+    pub fn from_project(project: &Path, contract: &Contract, batch: usize) -> Result<Self> {
+        let out = project.join("out").join("debug");
+        for entry in std::fs::read_dir(&out)? {
+            let path = entry?.path();
+            if path.file_name().is_some_and(|name| name.to_string_lossy().ends_with("-abi.json")) {
+                return Self::from_compiled(contract, &std::fs::read_to_string(&path)?, batch);
+            }
+        }
+        Err(SolverError::Deploy(format!("no contract ABI in {:?}", out)))
+    }
+
+    /// The same variant, decoding predicate data with `abi`.
+    pub fn with_abi(mut self, abi: ContractABI) -> Self {
+        self.abi = Some(abi);
+        self
+    }
+
+    /// The ABI the contract was compiled with, if it is known.
+    pub fn abi(&self) -> Option<&ContractABI> {
+        self.abi.as_ref()
+    }
+
+    /// Name of the predicate of this contract `address` points to.
+    pub fn predicate_name(&self, address: &PredicateAddress) -> Option<&str> {
+        self.predicates.iter().find(|(_, predicate)| predicate == address).map(|(name, _)| name.as_str())
+    }
+
+    /// Address of the predicate called `name`, e.g. `"settle"`.
+    pub fn predicate(&self, name: &str) -> Result<PredicateAddress> {
+        self.predicates
+            .iter()
+            .find(|(predicate, _)| predicate == name)
+            .map(|(_, address)| address.clone())
+            .ok_or_else(|| SolverError::Deploy(format!("contract has no predicate {}", name)))
+    }
+
+    /// Points a solution built for the standard contract at the same predicate of this one.
+    /// Solutions for predicates the standard contract doesn't have are left as they are.
+    pub fn retarget(&self, mut solution: Solution) -> Result<Solution> {
+        let name = predicate_name(&solution.predicate_to_solve);
+        if name != "unknown" {
+            solution.predicate_to_solve = self.predicate(name)?;
+        }
+        Ok(solution)
+    }
+}
+
+// Length of the `new_orders` array `addLimitOrdersBid` takes.
+fn insert_run(abi: &ContractABI) -> Result<usize> {
+    abi.predicates
+        .iter()
+        .find(|predicate| predicate.name.trim_start_matches("::") == "addLimitOrdersBid")
+        .and_then(|predicate| predicate.params.iter().find(|param| param.name.trim_start_matches("::") == "new_orders"))
+        .and_then(|param| match param.ty {
+            TypeABI::Array { size, .. } => usize::try_from(size).ok(),
+            _ => None,
+        })
+        .ok_or_else(|| SolverError::Deploy("ABI has no addLimitOrdersBid new_orders array".to_string()))
+}
+
+/// The contract source with `settle` and `settleMarketOrders` taking `batch` orders per side
+/// and `addLimitOrdersBid` and `addLimitOrdersAsk` runs of `insert_run` orders.
+pub fn render_source(source: &str, batch: usize, insert_run: usize) -> Result<String> {
+    if batch == 0 || insert_run == 0 {
+        return Err(SolverError::Deploy("batch size and run length must be at least 1".to_string()));
+    }
+    let arrays = Regex::new(r"\b(settle_order|market_order)\[\d+\]").unwrap();
+    let runs = Regex::new(r"\badd_order\[\d+\]").unwrap();
+    let arm = Regex::new(r"^(\s*)(partial_(?:bid|ask)_index) == (\d+) => (\w+)\[\d+\],\s*$").unwrap();
+
+    let mut lines = vec![];
+    for line in source.lines() {
+        let Some(captures) = arm.captures(line) else {
+            let line = arrays.replace_all(line, format!("${{1}}[{}]", batch));
+            lines.push(runs.replace_all(&line, format!("add_order[{}]", insert_run)).into_owned());
+            continue;
+        };
+        // every cond is regenerated from its first arm, the others are dropped
+        if &captures[3] != "0" {
+            continue;
+        }
+        let (indent, index, array) = (&captures[1], &captures[2], &captures[4]);
+        for i in 0..batch {
+            lines.push(format!("{}{} == {} => {}[{}],", indent, index, i, array, i));
+        }
+    }
+    let mut rendered = lines.join("\n");
+    if source.ends_with('\n') {
+        rendered.push('\n');
+    }
+    Ok(rendered)
+}
+
+/// Writes an N-wide copy of the pint project at `project` into a sibling directory, so its
+/// relative dependencies still resolve, and returns the new project's path.
+pub fn write_project(project: &Path, batch: usize, insert_run: usize) -> Result<PathBuf> {
+    let name = project
+        .file_name()
+        .ok_or_else(|| SolverError::Deploy(format!("{:?} is not a project directory", project)))?;
+    let target = project.with_file_name(format!("{}_{}_{}", name.to_string_lossy(), batch, insert_run));
+    std::fs::create_dir_all(target.join("src"))?;
+    std::fs::copy(project.join("pint.toml"), target.join("pint.toml"))?;
+    for entry in std::fs::read_dir(project.join("src"))? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "pnt") {
+            let source = std::fs::read_to_string(&path)?;
+            let file_name = path.file_name().unwrap_or_default();
+            std::fs::write(target.join("src").join(file_name), render_source(&source, batch, insert_run)?)?;
+        }
+    }
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use essential_types::Word;
+
+    const SOURCE: &str = "predicate settle(
+    bid_orders: settle_order[10],
+    bid_market_orders: market_order[10],
+    solver_orders: order[2]
+    )
+    {
+    let partial_bid_order: settle_order = cond{
+        partial_bid_index == 0 => bid_orders[0],
+        partial_bid_index == 1 => bid_orders[1],
+        partial_bid_index == 2 => bid_orders[2],
+        else => bid_orders[0]
+    };
+}
+
+predicate addLimitOrdersBid(leading_key: int, trailing_key: int, new_orders: add_order[5]){
+}
+";
+
+    #[test]
+    fn renders_wider_and_narrower_batches() {
+        let rendered = render_source(SOURCE, 4, 3).unwrap();
+        assert!(rendered.contains("bid_orders: settle_order[4],"));
+        assert!(rendered.contains("new_orders: add_order[3]){"));
+        assert!(rendered.contains("bid_market_orders: market_order[4],"));
+        assert!(rendered.contains("solver_orders: order[2]"));
+        assert!(rendered.contains("        partial_bid_index == 3 => bid_orders[3],\n        else => bid_orders[0]"));
+        assert!(rendered.ends_with("}\n"));
+
+        let narrow = render_source(SOURCE, 1, 5).unwrap();
+        assert!(narrow.contains("partial_bid_index == 0 => bid_orders[0],\n        else"));
+        assert!(!narrow.contains("partial_bid_index == 1"));
+        assert!(narrow.contains("new_orders: add_order[5]){"));
+        assert!(render_source(SOURCE, 0, 5).is_err());
+        assert!(render_source(SOURCE, 4, 0).is_err());
+    }
+
+    #[test]
+    fn standard_variant_retargets_to_itself() {
+        let variant = ContractVariant::standard();
+        assert_eq!(variant.batch, SETTLE_BATCH);
+        assert_eq!(variant.insert_run, INSERT_RUN);
+        let abi = std::fs::read_to_string("../PintLOB/orderbook/out/debug/orderbook-abi.json").unwrap();
+        assert_eq!(insert_run(&serde_json::from_str(&abi).unwrap()).unwrap(), INSERT_RUN);
+        assert_eq!(variant.predicate("settle").unwrap(), settle::ADDRESS);
+        let solution = crate::solutions::produce_solution_deposit(1, 1, 0, 0, [1; 4], [1; 4], [1; 4]);
+        assert_eq!(variant.retarget(solution.clone()).unwrap(), solution);
+    }
+
+    #[test]
+    fn batch_builders_match_the_generated_abi() {
+        use crate::solutions::*;
+        let variant = ContractVariant::standard();
+        let settle_orders: [SettleOrder; 10] = std::array::from_fn(|i| SettleOrder { index: i as i64 + 1, auth: [i as i64; 4] });
+        let market_orders: [MarketOrder; 10] = std::array::from_fn(|i| MarketOrder { amount: i as i64, addr: [7; 4], auth: [8; 4] });
+        let order = LimitOrder { max_amnt: 6, price: 101, is_bid: true, addr: [1; 4], auth: [2; 4], next_key: 3 };
+        let orders = [order; 10];
+        // a trader only takes one slot, so the ask side has its own addresses but the solver
+        let addrs: [[Word; 4]; 11] = std::array::from_fn(|i| [i as i64; 4]);
+        let ask_addrs: [[Word; 4]; 11] = std::array::from_fn(|i| if i == 10 { addrs[10] } else { [i as i64 + 40; 4] });
+        let amounts: [i64; 11] = std::array::from_fn(|i| i as i64 * 100);
+        let balances = crate::book::Balances::new();
+
+        let batch = produce_solution_settle_batch(
+            &variant, 4, 5, 2, 3, &settle_orders, &settle_orders, [order; 2],
+            &balances, &addrs, &ask_addrs, &amounts, &amounts, &amounts, &amounts, 1, 2, &orders, &orders,
+        )
+        .unwrap();
+        // the generated vars lay the predicate data out the way the batch builders do
+        let settle_orders_tuple = settle_orders.map(|o| (o.index, o.auth));
+        let solver_orders_tuple = [order; 2].map(|o| (o.max_amnt, o.price, o.is_bid, o.addr, o.auth, o.next_key));
+        let vars = settle::Vars {
+            partial_amount_bid: 4,
+            partial_amount_ask: 5,
+            partial_bid_index: 2,
+            partial_ask_index: 3,
+            bid_orders: settle_orders_tuple,
+            ask_orders: settle_orders_tuple,
+            solver_orders: solver_orders_tuple,
+        };
+        assert_eq!(batch.predicate_data, Vec::from(vars));
+
+        let market_addrs: [[Word; 4]; 10] = std::array::from_fn(|i| [i as i64 + 20; 4]);
+        let ask_market_addrs: [[Word; 4]; 10] = std::array::from_fn(|i| [i as i64 + 60; 4]);
+        let market_amounts: [i64; 10] = std::array::from_fn(|i| i as i64);
+        let batch = produce_solution_market_order_batch(
+            &variant, 4, 5, 2, 3, &settle_orders, &settle_orders, &market_orders, &market_orders, 99, 101, [order; 2],
+            &balances, &addrs, &ask_addrs, &market_addrs, &ask_market_addrs, &amounts, &amounts, &amounts, &amounts,
+            &market_amounts, &market_amounts, &market_amounts, &market_amounts, 1, 2, &orders, &orders,
+        )
+        .unwrap();
+        let market_orders_tuple = market_orders.map(|o| (o.amount, o.addr, o.auth));
+        let vars = settleMarketOrders::Vars {
+            partial_amount_bid: 4,
+            partial_amount_ask: 5,
+            partial_bid_index: 2,
+            partial_ask_index: 3,
+            bid_market_orders: market_orders_tuple,
+            ask_market_orders: market_orders_tuple,
+            bid_limit_orders: settle_orders_tuple,
+            ask_limit_orders: settle_orders_tuple,
+            average_price_bids: 99,
+            average_price_asks: 101,
+            solver_orders: solver_orders_tuple,
+        };
+        assert_eq!(batch.predicate_data, Vec::from(vars));
+
+        let short = produce_solution_settle_batch(
+            &variant, 4, 5, 2, 3, &settle_orders[..5], &settle_orders, [order; 2],
+            &balances, &addrs, &ask_addrs, &amounts, &amounts, &amounts, &amounts, 1, 2, &orders, &orders,
+        );
+        assert!(matches!(short, Err(SolverError::Validation(_))));
+    }
+}